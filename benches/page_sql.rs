@@ -0,0 +1,75 @@
+// page() 构建 SQL 的开销对比，运行: cargo bench --bench page_sql
+// before: 旧实现，复制整个 wrapper 后设置 limit / offset 再构建
+// after: 当前实现，构建时直接传入分页参数
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use futures::executor::block_on;
+use rbatis::async_trait;
+use rbatis::rbdc::db::ExecResult;
+use rbatis::Error;
+use rbatis_wrapper::{Executor, Page, QueryWrapper};
+use rbs::Value;
+
+// 统计查询返回 1000，数据查询返回空数组，只测量构建和调度的开销
+struct NoopExecutor;
+
+#[async_trait]
+impl Executor for NoopExecutor {
+    async fn query(&self, sql: &str, _params: Vec<Value>) -> Result<Value, Error> {
+        if sql.starts_with("SELECT COUNT(*)") {
+            return Ok(Value::U64(1000));
+        }
+        Ok(Value::Array(vec![]))
+    }
+
+    async fn exec(&self, _sql: &str, _params: Vec<Value>) -> Result<ExecResult, Error> {
+        Ok(ExecResult {
+            rows_affected: 0,
+            last_insert_id: Value::Null,
+        })
+    }
+}
+
+fn wrapper() -> QueryWrapper {
+    let mut wrapper = QueryWrapper::new()
+        .select(vec!["m.id", "m.name", "m.created_at", "o.total"])
+        .inner_join("orders o", "o.member_id = m.id")
+        .order_by("m.created_at", false);
+    for i in 0..20 {
+        wrapper = wrapper.eq(&format!("m.col{}", i), i);
+    }
+    wrapper
+}
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut()) -> Duration {
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    let per_iter = start.elapsed() / iterations;
+    println!("{:<8} {:>10?} / iter", name, per_iter);
+    per_iter
+}
+
+fn main() {
+    let rb = NoopExecutor;
+    let wrapper = wrapper();
+    let (page_no, page_size) = (3u64, 20u64);
+    let iterations = 20_000;
+
+    let before = bench("before", iterations, || {
+        let total = block_on(wrapper.count(&rb, "member m")).unwrap();
+        let paged = wrapper.clone().limit(page_size).offset((page_no - 1) * page_size);
+        let records: Vec<serde_json::Value> = block_on(paged.query(&rb, "member m")).unwrap();
+        black_box(Page::new(records, total, page_no, page_size));
+    });
+    let after = bench("after", iterations, || {
+        let page: Page<serde_json::Value> = block_on(wrapper.page(&rb, "member m", page_no, page_size)).unwrap();
+        black_box(page);
+    });
+    println!("after / before: {:.2}", after.as_secs_f64() / before.as_secs_f64());
+}
@@ -0,0 +1,81 @@
+use rbatis::rbdc::db::ExecResult;
+use rbatis::Error;
+use rbatis::RBatis;
+use rbs::Value;
+
+use crate::{Executor, QueryWrapper};
+
+// 批量执行的单条语句
+#[derive(Clone, Debug)]
+enum Statement {
+    Delete(QueryWrapper, String),
+    Exec(QueryWrapper, String, Vec<Value>, String),
+    Raw(String, Vec<Value>),
+}
+
+// 收集多条更新语句，在同一个事务中按添加顺序执行，任意一条失败时回滚全部
+#[derive(Clone, Debug, Default)]
+pub struct BatchExecutor {
+    statements: Vec<Statement>,
+}
+
+// 批量执行的结果，results 与添加语句的顺序一致
+#[derive(Debug)]
+pub struct BatchResult {
+    pub results: Vec<ExecResult>,
+    pub rows_affected: u64, // 所有语句影响的总行数
+}
+
+impl BatchExecutor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 添加按 wrapper 条件删除的语句，与 QueryWrapper::delete 相同，没有条件时执行会返回错误
+    pub fn delete(mut self, wrapper: QueryWrapper, table_name: &str) -> Self {
+        self.statements.push(Statement::Delete(wrapper, table_name.to_string()));
+        self
+    }
+
+    // 添加调用方提供语句开头、条件来自 wrapper 的语句，与 QueryWrapper::exec 相同
+    pub fn exec(mut self, wrapper: QueryWrapper, sql_prefix: &str, params: Vec<Value>, table_name: &str) -> Self {
+        self.statements.push(Statement::Exec(wrapper, sql_prefix.to_string(), params, table_name.to_string()));
+        self
+    }
+
+    // 添加原生语句，? 按顺序绑定 params
+    pub fn raw(mut self, sql: &str, params: Vec<Value>) -> Self {
+        self.statements.push(Statement::Raw(sql.to_string(), params));
+        self
+    }
+
+    // 语句数量
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+
+    // 在一个事务中依次执行所有语句，全部成功时提交，第一条失败的语句会回滚整个事务
+    // 返回的错误中包含失败语句的序号 (从 0 开始)
+    pub async fn execute(self, rb: &RBatis) -> Result<BatchResult, Error> {
+        QueryWrapper::transaction(rb, |tx| async move {
+            let mut results = Vec::with_capacity(self.statements.len());
+            for (i, statement) in self.statements.into_iter().enumerate() {
+                let result = match statement {
+                    Statement::Delete(wrapper, table_name) => wrapper.delete(&tx, &table_name).await,
+                    Statement::Exec(wrapper, sql_prefix, params, table_name) => {
+                        wrapper.exec(&tx, &sql_prefix, params, &table_name).await
+                    }
+                    Statement::Raw(sql, params) => Executor::exec(&tx, &sql, params).await,
+                };
+                results.push(result.map_err(|e| Error::from(format!("batch statement {} failed: {}", i, e)))?);
+            }
+            let rows_affected = results.iter().map(|r| r.rows_affected).sum();
+            Ok(BatchResult { results, rows_affected })
+        })
+        .await
+    }
+}
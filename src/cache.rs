@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rbatis::async_trait;
+
+// page_cached 使用的总数缓存，key 由统计SQL和绑定参数生成，失效由调用方负责
+#[async_trait]
+pub trait CountCache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<u64>;
+    async fn put(&self, key: &str, total: u64, ttl: Duration);
+}
+
+// 进程内的总数缓存，过期的条目在读取时删除
+#[derive(Debug, Default)]
+pub struct MemoryCountCache {
+    entries: Mutex<HashMap<String, (u64, Instant)>>,
+}
+
+impl MemoryCountCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 清空所有缓存，数据变更后由调用方使用
+    pub fn clear(&self) {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[async_trait]
+impl CountCache for MemoryCountCache {
+    async fn get(&self, key: &str) -> Option<u64> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get(key) {
+            Some((total, expires_at)) if *expires_at > Instant::now() => Some(*total),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    async fn put(&self, key: &str, total: u64, ttl: Duration) {
+        let expires_at = Instant::now() + ttl;
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string(), (total, expires_at));
+    }
+}
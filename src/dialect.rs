@@ -0,0 +1,8 @@
+// 数据库方言，用于生成不同数据库下语法不同的片段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    #[default]
+    MySql,
+    Postgres,
+    Sqlite,
+}
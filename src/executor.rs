@@ -0,0 +1,29 @@
+use rbatis::async_trait;
+use rbatis::rbdc::db::ExecResult;
+use rbatis::Error;
+use rbs::Value;
+
+// QueryWrapper 执行 SQL 使用的接口，RBatis、事务、连接等 rbatis Executor 都已实现
+// 单元测试中可以实现该 trait 记录生成的 SQL 和参数，不需要真实的数据库
+#[async_trait]
+pub trait Executor: Send + Sync {
+    // 执行查询，返回行数组
+    async fn query(&self, sql: &str, params: Vec<Value>) -> Result<Value, Error>;
+
+    // 执行更新语句
+    async fn exec(&self, sql: &str, params: Vec<Value>) -> Result<ExecResult, Error>;
+}
+
+#[async_trait]
+impl<T> Executor for T
+where
+    T: rbatis::executor::Executor + ?Sized,
+{
+    async fn query(&self, sql: &str, params: Vec<Value>) -> Result<Value, Error> {
+        rbatis::executor::Executor::query(self, sql, params).await
+    }
+
+    async fn exec(&self, sql: &str, params: Vec<Value>) -> Result<ExecResult, Error> {
+        rbatis::executor::Executor::exec(self, sql, params).await
+    }
+}
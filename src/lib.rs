@@ -1,3 +1,13 @@
+pub mod batch;
+pub mod cache;
+pub mod dialect;
+pub mod executor;
+pub mod router;
 pub mod wrapper;
 
-pub use wrapper::*;
\ No newline at end of file
+pub use batch::*;
+pub use cache::*;
+pub use dialect::*;
+pub use executor::*;
+pub use router::*;
+pub use wrapper::*;
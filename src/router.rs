@@ -0,0 +1,92 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rbatis::async_trait;
+use rbatis::rbdc::db::ExecResult;
+use rbatis::Error;
+use rbatis::RBatis;
+use rbs::Value;
+
+// 读写分离的路由，作为 Executor 传给 QueryWrapper 的执行方法：
+// 查询轮流使用只读副本，更新语句 (delete / exec 等) 和加锁的查询始终使用主库
+// 需要读到刚写入的数据或使用事务时，直接使用 writer()
+#[derive(Debug)]
+pub struct DbRouter {
+    writer: RBatis,
+    readers: Vec<RBatis>,
+    next: AtomicUsize,
+}
+
+impl DbRouter {
+    // readers 为空时查询也使用主库
+    pub fn new(writer: RBatis, readers: Vec<RBatis>) -> Self {
+        Self {
+            writer,
+            readers,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // 主库
+    pub fn writer(&self) -> &RBatis {
+        &self.writer
+    }
+
+    // 按轮询选择下一个只读副本
+    pub fn reader(&self) -> &RBatis {
+        if self.readers.is_empty() {
+            return &self.writer;
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        &self.readers[i]
+    }
+}
+
+// SELECT ... FOR UPDATE / FOR SHARE 等加锁查询必须在主库执行
+fn is_locking_read(sql: &str) -> bool {
+    let upper = sql.to_ascii_uppercase();
+    upper.contains(" FOR UPDATE") || upper.contains(" FOR SHARE") || upper.contains(" LOCK IN SHARE MODE")
+}
+
+#[async_trait]
+impl crate::Executor for DbRouter {
+    async fn query(&self, sql: &str, params: Vec<Value>) -> Result<Value, Error> {
+        let rb = if is_locking_read(sql) { self.writer() } else { self.reader() };
+        rbatis::executor::Executor::query(rb, sql, params).await
+    }
+
+    async fn exec(&self, sql: &str, params: Vec<Value>) -> Result<ExecResult, Error> {
+        rbatis::executor::Executor::exec(self.writer(), sql, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readers_are_used_round_robin() {
+        let router = DbRouter::new(RBatis::new(), vec![RBatis::new(), RBatis::new()]);
+        let picked: Vec<usize> = (0..4)
+            .map(|_| {
+                let rb = router.reader();
+                router.readers.iter().position(|r| std::ptr::eq(r, rb)).unwrap()
+            })
+            .collect();
+        assert_eq!(picked, vec![0, 1, 0, 1]);
+
+        let router = DbRouter::new(RBatis::new(), vec![]);
+        assert!(std::ptr::eq(router.reader(), router.writer()));
+    }
+
+    #[test]
+    fn locking_reads_go_to_writer() {
+        assert!(is_locking_read("SELECT * FROM t WHERE id = ? FOR UPDATE"));
+        assert!(is_locking_read("select * from t for update skip locked"));
+        assert!(is_locking_read("SELECT * FROM t FOR SHARE"));
+        assert!(is_locking_read("SELECT * FROM t LOCK IN SHARE MODE"));
+        assert!(!is_locking_read("SELECT * FROM t WHERE note = ?"));
+        assert!(!is_locking_read("SELECT * FROM t ORDER BY id"));
+        let sql = crate::QueryWrapper::new().eq("status", 0).for_update_skip_locked().build_sql("jobs");
+        assert!(is_locking_read(&sql), "{}", sql);
+    }
+}
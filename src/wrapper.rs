@@ -1,70 +1,365 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::time::Duration;
+
+use futures::Stream;
+use rbatis::executor::RBatisTxExecutor;
 use rbatis::RBatis;
 use rbatis::Error;
-use serde::Serialize;
+use rbs::Value;
+use serde::{Deserialize, Serialize};
+
+use crate::{CountCache, Dialect, Executor};
+
+// 执行类方法的返回结果，包含影响行数和最后插入的 id
+pub use rbatis::rbdc::db::ExecResult;
 
 // 添加分页结果结构体
-#[derive(Debug, Serialize)]
+/// 可以直接遍历分页结果中的记录:
+/// ```
+/// use rbatis_wrapper::Page;
+///
+/// let page = Page::new(vec![1, 2, 3], 3, 1, 10);
+/// assert_eq!(page.len(), 3);
+/// for id in &page {
+///     assert!(*id > 0);
+/// }
+/// let doubled: Vec<i32> = page.into_iter().map(|id| id * 2).collect();
+/// assert_eq!(doubled, vec![2, 4, 6]);
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
 pub struct Page<T> {
     pub records: Vec<T>,         // 数据列表
+    #[serde(default)]
+    pub current_size: usize,    // 本页实际返回的记录数，最后一页可能小于 page_size
     pub total: u64,             // 总记录数
     pub page_no: u64,           // 当前页码
     pub page_size: u64,         // 每页大小
     pub pages: u64,             // 总页数
     pub has_next: bool,         // 是否有下一页
+    #[serde(default)]
+    pub has_prev: bool,         // 是否有上一页
+    #[serde(default)]
+    pub total_is_estimate: bool, // total 是否为估算值 (page_estimated)
 }
 
 impl<T> Page<T> {
     pub fn new(records: Vec<T>, total: u64, page_no: u64, page_size: u64) -> Self {
-        let pages = (total + page_size - 1) / page_size;
+        // page_size 为 0 时没有有效的分页，总页数记为 0
+        let pages = if page_size == 0 { 0 } else { total.div_ceil(page_size) };
         let has_next = page_no < pages;
-        
+        let has_prev = page_no > 1;
+
         Self {
+            current_size: records.len(),
             records,
             total,
             page_no,
             page_size,
             pages,
             has_next,
+            has_prev,
+            total_is_estimate: false,
+        }
+    }
+
+    // 没有数据的空页
+    pub fn empty(page_no: u64, page_size: u64) -> Self {
+        Self::new(vec![], 0, page_no, page_size)
+    }
+
+    // 只有一条记录的页 (第 1 页，每页 1 条，共 1 页)
+    pub fn single(record: T) -> Self {
+        Self::new(vec![record], 1, 1, 1)
+    }
+
+    // 从内存中的完整列表截取指定页，适用于数据来自缓存或其它服务的场景，页码 0 按第一页处理
+    pub fn from_full_list(all: Vec<T>, page_no: u64, page_size: u64) -> Self {
+        let page_no = page_no.max(1);
+        let total = all.len() as u64;
+        let skip = (page_no - 1).saturating_mul(page_size);
+        let records = all
+            .into_iter()
+            .skip(usize::try_from(skip).unwrap_or(usize::MAX))
+            .take(usize::try_from(page_size).unwrap_or(usize::MAX))
+            .collect();
+        Self::new(records, total, page_no, page_size)
+    }
+
+    // 是否为第一页
+    pub fn is_first_page(&self) -> bool {
+        !self.has_prev
+    }
+
+    // 是否为最后一页 (没有数据时同时也是第一页)
+    pub fn is_last_page(&self) -> bool {
+        !self.has_next
+    }
+
+    // 本页记录数
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    // 本页是否没有记录
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // 本页记录
+    pub fn records(&self) -> &[T] {
+        &self.records
+    }
+
+    // 取出本页记录，丢弃分页信息
+    pub fn into_records(self) -> Vec<T> {
+        self.records
+    }
+
+    // 转换记录类型，分页信息保持不变
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            records: self.records.into_iter().map(f).collect(),
+            current_size: self.current_size,
+            total: self.total,
+            page_no: self.page_no,
+            page_size: self.page_size,
+            pages: self.pages,
+            has_next: self.has_next,
+            has_prev: self.has_prev,
+            total_is_estimate: self.total_is_estimate,
+        }
+    }
+
+    // 可失败的记录类型转换，遇到第一个错误时立即返回
+    pub fn try_map<U, E>(self, f: impl FnMut(T) -> Result<U, E>) -> Result<Page<U>, E> {
+        Ok(Page {
+            records: self.records.into_iter().map(f).collect::<Result<Vec<U>, E>>()?,
+            current_size: self.current_size,
+            total: self.total,
+            page_no: self.page_no,
+            page_size: self.page_size,
+            pages: self.pages,
+            has_next: self.has_next,
+            has_prev: self.has_prev,
+            total_is_estimate: self.total_is_estimate,
+        })
+    }
+
+    // 借助已有的 TryFrom 实现转换记录类型，遇到第一个转换错误时立即返回
+    pub fn try_convert<U: TryFrom<T>>(self) -> Result<Page<U>, U::Error> {
+        self.try_map(U::try_from)
+    }
+}
+
+// 分页请求参数，通常从查询字符串反序列化，缺省为第 1 页、每页 20 条
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct PageRequest {
+    #[serde(default = "PageRequest::default_page_no")]
+    pub page_no: u64,           // 页码，从 1 开始
+    #[serde(default = "PageRequest::default_page_size")]
+    pub page_size: u64,         // 每页大小
+    #[serde(skip, default = "PageRequest::default_max_page_size")]
+    pub max_page_size: u64,     // 每页大小上限，不从请求中读取
+}
+
+impl Default for PageRequest {
+    fn default() -> Self {
+        Self {
+            page_no: Self::default_page_no(),
+            page_size: Self::default_page_size(),
+            max_page_size: Self::default_max_page_size(),
+        }
+    }
+}
+
+impl PageRequest {
+    pub fn new(page_no: u64, page_size: u64) -> Self {
+        Self { page_no, page_size, ..Self::default() }
+    }
+
+    // 设置每页大小上限，默认 200
+    pub fn with_max_page_size(mut self, max_page_size: u64) -> Self {
+        self.max_page_size = max_page_size;
+        self
+    }
+
+    // 修正请求参数: 页码 0 按第 1 页处理，每页大小为 0 时使用默认值，超过上限时取上限
+    pub fn normalize(mut self) -> Self {
+        self.page_no = self.page_no.max(1);
+        if self.page_size == 0 {
+            self.page_size = Self::default_page_size();
+        }
+        if self.max_page_size > 0 {
+            self.page_size = self.page_size.min(self.max_page_size);
         }
+        self
+    }
+
+    fn default_page_no() -> u64 {
+        1
+    }
+
+    fn default_page_size() -> u64 {
+        20
+    }
+
+    fn default_max_page_size() -> u64 {
+        200
+    }
+}
+
+// 游标分页结果，next_cursor 为本页最后一行的排序键，传给下一次 page_after 获取下一页
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct CursorPage<T, C> {
+    pub records: Vec<T>,            // 数据列表
+    pub next_cursor: Option<C>,     // 下一页游标，没有数据时为 None
+    pub has_next: bool,             // 是否有下一页
+}
+
+impl<T> IntoIterator for Page<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Page<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.records.iter()
+    }
+}
+
+// 请求页码超出总页数时的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PageOverflow {
+    #[default]
+    Empty, // 返回空数据，page_no 保持请求的页码
+    Last,  // 返回最后一页，page_no 为实际返回的页码
+}
+
+// 排序方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Order {
+    // 是否为升序
+    pub fn is_asc(self) -> bool {
+        self == Order::Asc
     }
 }
 
+// 排序时 NULL 值的位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NullsOrder {
+    First,
+    Last,
+}
+
 /// like mybatis plus
 /// for example:
-/// ```
+/// ```ignore
 /// let count = QueryWrapper::new()
 ///     .custom_sql("select count(*) from member")
-///     .get_one::<u64>(&RB, "")
+///     .get_one::<u64>(&*RB, "")
 ///     .await?;
 /// println!("count: {:?}", count);
-
+///
 /// #[derive(serde::Deserialize, serde::Serialize, Debug)]
 /// struct Member {
 ///     id: u64,
 ///     email: Option<String>
 /// }
-
+///
 /// let member = QueryWrapper::new()
 ///     .eq("id", 7386)
-///     .get_one::<Member>(&RB, "member")
+///     .get_one::<Member>(&*RB, "member")
 ///     .await?;
 /// println!("member: {:?}", member);
-
+///
+/// let members = QueryWrapper::new()
+///     .gt("id", 7000)
+///     .order_by("id", false)
+///     .limit(10)
+///     .offset(20)
+///     .query::<Member>(&*RB, "member")
+///     .await?;
+///
 /// Ok(Json(json!({
 ///     "code": 0,
 ///     "data": member,
+///     "list": members,
 ///     "count": count,
 /// })))
 /// ```
 #[derive(Default, Debug, Clone)]
 pub struct QueryWrapper {
     where_conditions: Vec<String>,
+    where_params: Vec<Value>,      // WHERE 条件中绑定的参数
     order_by: Vec<String>,
     select_columns: Vec<String>,
     limit: Option<u64>,
     offset: Option<u64>,
     custom_sql: Option<String>,    // 添加自定义SQL支持
+    custom_params: Vec<Value>,     // 自定义SQL中绑定的参数
     join_conditions: Vec<String>,  // 添加JOIN条件支持
+    join_params: Vec<Value>,       // JOIN 条件中绑定的参数
+    group_by: Vec<String>,         // 分组列
+    rollup: bool,                  // 分组是否生成小计行 (ROLLUP)
+    dialect: Dialect,              // 数据库方言
+    dialect_rendered: bool,        // 是否已按当前方言生成了方言相关的片段，之后不能再切换方言
+    from_source: Option<String>,   // 替代表名的查询来源，如派生表
+    from_params: Vec<Value>,       // 派生表中绑定的参数
+    ctes: Vec<String>,             // 公共表表达式 (WITH)，按声明顺序
+    cte_params: Vec<Value>,        // 公共表表达式中绑定的参数
+    recursive: bool,               // 是否为 WITH RECURSIVE
+    alias: Option<String>,         // 主表别名
+    unions: Vec<(String, String)>, // UNION / UNION ALL 分支 (连接方式, 分支语句)
+    union_branch_paged: bool,      // 是否有分支带 ORDER BY / LIMIT (SQLite 不支持)
+    union_params: Vec<Value>,      // UNION 分支中绑定的参数
+    error: Option<String>,         // 构建过程中发现的错误，执行时返回
+    sortable_columns: Option<Vec<String>>, // 允许排序的列，None 表示不限制
+    order_columns: Vec<String>,    // 排序方法引用的列或表达式，由 validate() 检查 sortable_columns
+    select_params: Vec<Value>,     // 查询列中子查询绑定的参数
+    strict: bool,                  // 严格模式，空值集合等可疑条件在执行时返回错误
+    empty_value_sets: Vec<String>, // 收到空值集合的条件列
+    max_limit: Option<u64>,        // 查询记录数上限
+    page_overflow: PageOverflow,   // 页码超出总页数时的处理方式
+    skip_count: bool,              // page() 是否跳过总数查询
+    count_sql_override: Option<String>, // 自定义统计SQL
+    count_sql_where: bool,         // 自定义统计SQL是否追加 WHERE 条件
+    distinct_on: Vec<String>,      // Postgres DISTINCT ON 列
+    default_order: Option<String>, // 分页时没有排序时使用的默认排序
+    require_order: bool,           // 分页时是否必须指定排序
+    deep_offset_pk: Option<String>, // 深分页优化使用的主键列
+    redact_params: bool,           // 日志中隐藏参数值
+    concurrent_page: bool,         // page() 是否同时执行统计和数据查询
+    retry_policy: Option<RetryPolicy>, // 临时性错误的重试策略
+    idempotent: bool,              // 更新语句是否可以安全重试
+    timeout: Option<Duration>,     // 单条语句的执行超时
+    window_count: bool,            // page() 是否用 COUNT(*) OVER() 统计总数
+    quote_table: bool,             // 表名是否加引号
+    having: Vec<String>,           // HAVING 条件
+    having_params: Vec<Value>,     // HAVING 条件的绑定参数
+    allow_delete_all: bool,        // 是否允许没有条件的删除
+    skip_locked: bool,             // 是否追加 FOR UPDATE SKIP LOCKED
+    table_suffix: Option<String>,  // 分表后缀，拼接在主表名之后
+    set_values: Vec<(String, Value)>, // update 的 SET 列和值，按设置顺序
+    conflict_columns: Vec<String>, // upsert 的冲突列
+    conflict_update: Option<Vec<String>>, // upsert 冲突时更新的列，None 为除冲突列外的插入列，空为 DO NOTHING
 }
 
 impl QueryWrapper {
@@ -72,239 +367,3960 @@ impl QueryWrapper {
         Self::default()
     }
 
-    // 等于条件
-    pub fn eq<T: ToString>(mut self, column: &str, value: T) -> Self {
-        self.where_conditions.push(format!("{} = '{}'", column, value.to_string()));
+    // 允许没有条件的 delete / delete_in_chunks / update 作用于整张表的数据，默认不允许
+    pub fn allow_delete_all(mut self) -> Self {
+        self.allow_delete_all = true;
         self
     }
 
-    // 不等于条件
-    pub fn ne<T: ToString>(mut self, column: &str, value: T) -> Self {
-        self.where_conditions.push(format!("{} != '{}'", column, value.to_string()));
+    // 设置 update 更新的列和值，同一列多次设置时使用最后一次的值
+    pub fn set<T: Into<Value>>(mut self, column: &str, value: T) -> Self {
+        self.push_set(column, value.into());
         self
     }
 
-    // 大于条件
-    pub fn gt<T: ToString>(mut self, column: &str, value: T) -> Self {
-        self.where_conditions.push(format!("{} > '{}'", column, value.to_string()));
+    // 选择性更新：实体中有值的字段作为 update 更新的列，值为 None 的字段跳过
+    // 可以和 set 混用，同一列以最后设置的为准
+    pub fn set_entity<T: Serialize>(mut self, entity: &T) -> Self {
+        match entity_columns("set_entity", entity) {
+            Ok(columns) => columns.into_iter().for_each(|(column, value)| self.push_set(&column, value)),
+            Err(e) => self.set_error(e.to_string()),
+        }
         self
     }
 
-    // 小于条件
-    pub fn lt<T: ToString>(mut self, column: &str, value: T) -> Self {
-        self.where_conditions.push(format!("{} < '{}'", column, value.to_string()));
+    // upsert 的冲突列，渲染为 Postgres/SQLite 的 ON CONFLICT (...)
+    // MySQL 的 ON DUPLICATE KEY UPDATE 按表上的主键和唯一索引判断冲突，不使用这里的列
+    pub fn on_conflict(mut self, columns: Vec<&str>) -> Self {
+        self.conflict_columns = columns.into_iter().map(String::from).collect();
         self
     }
 
-    // LIKE 条件
-    pub fn like(mut self, column: &str, value: &str) -> Self {
-        self.where_conditions.push(format!("{} LIKE '%{}%'", column, value));
+    // upsert 冲突时用插入的值更新这些列，不调用时更新除冲突列外的所有插入列
+    pub fn do_update(mut self, columns: Vec<&str>) -> Self {
+        self.conflict_update = Some(columns.into_iter().map(String::from).collect());
         self
     }
 
-    // 指定查询列
-    pub fn select(mut self, columns: Vec<&str>) -> Self {
-        self.select_columns = columns.into_iter().map(String::from).collect();
+    // upsert 冲突时保留已有记录
+    pub fn do_nothing(mut self) -> Self {
+        self.conflict_update = Some(vec![]);
         self
     }
 
-    // 排序
-    pub fn order_by(mut self, column: &str, asc: bool) -> Self {
-        let order = if asc { "ASC" } else { "DESC" };
-        self.order_by.push(format!("{} {}", column, order));
+    fn push_set(&mut self, column: &str, value: Value) {
+        match self.set_values.iter_mut().find(|(c, _)| c == column) {
+            Some((_, v)) => *v = value,
+            None => self.set_values.push((column.to_string(), value)),
+        }
+    }
+
+    // 表名加上方言对应的引号 (MySQL 使用反引号，Postgres/SQLite 使用双引号)，用于与关键字同名或区分大小写的表名
+    pub fn quote_table_name(mut self, quote: bool) -> Self {
+        self.quote_table = quote;
         self
     }
 
-    // 修改 limit 方法为引用
-    pub fn limit(&mut self, limit: u64) -> &mut Self {
-        self.limit = Some(limit);
+    // 分表后缀，查询、统计、删除时拼接在主表名之后，如 table_suffix("_202401") 查询 events_202401
+    // JOIN 条件中的 {table} 会替换为拼接后缀后的主表名；后缀只允许字母、数字和下划线
+    pub fn table_suffix(mut self, suffix: &str) -> Self {
+        if !suffix.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+            self.set_error(format!("invalid table suffix `{}`", suffix));
+            return self;
+        }
+        self.table_suffix = Some(suffix.to_string());
         self
     }
 
-    // 修改 offset 方法为引用
-    pub fn offset(&mut self, offset: u64) -> &mut Self {
-        self.offset = Some(offset);
+    // 设置数据库方言，默认 MySQL
+    // like、ilike、order_by_random 等方法调用时就按当前方言生成片段，需要在这些方法之前设置，之后切换方言会记录错误
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        if self.dialect_rendered && dialect != self.dialect {
+            self.set_error(format!(
+                "dialect changed to {:?} after {:?}-specific SQL was built, call dialect() first",
+                dialect, self.dialect
+            ));
+        }
+        self.dialect = dialect;
         self
     }
 
-    // 添加自定义SQL方法
-    pub fn custom_sql(mut self, sql: &str) -> Self {
-        self.custom_sql = Some(sql.to_string());
+    // 生成方言相关的片段前调用，返回当前方言
+    fn render_dialect(&mut self) -> Dialect {
+        self.dialect_rendered = true;
+        self.dialect
+    }
+
+    // 等于条件
+    pub fn eq<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.push_condition(format!("{} = ?", column), value.into())
+    }
+
+    // 不等于条件
+    pub fn ne<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.push_condition(format!("{} != ?", column), value.into())
+    }
+
+    // 大于条件
+    pub fn gt<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.push_condition(format!("{} > ?", column), value.into())
+    }
+
+    // 小于条件
+    pub fn lt<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.push_condition(format!("{} < ?", column), value.into())
+    }
+
+    // IN 条件，值列表为空时不会丢弃条件，而是生成永远不成立的 1 = 0，避免误查全表
+    pub fn in_<T: Into<Value>>(mut self, column: &str, values: Vec<T>) -> Self {
+        if values.is_empty() {
+            self.empty_value_sets.push(column.to_string());
+            self.where_conditions.push("1 = 0".to_string());
+            return self;
+        }
+        let placeholders = vec!["?"; values.len()].join(", ");
+        self.where_conditions.push(format!("{} IN ({})", column, placeholders));
+        self.where_params.extend(values.into_iter().map(Into::into));
         self
     }
 
-    // 添加 INNER JOIN
-    pub fn inner_join(mut self, table: &str, on_condition: &str) -> Self {
-        self.join_conditions.push(format!("INNER JOIN {} ON {}", table, on_condition));
+    // NOT IN 条件，值列表为空时不排除任何记录
+    pub fn not_in<T: Into<Value>>(mut self, column: &str, values: Vec<T>) -> Self {
+        if values.is_empty() {
+            self.empty_value_sets.push(column.to_string());
+            return self;
+        }
+        let placeholders = vec!["?"; values.len()].join(", ");
+        self.where_conditions.push(format!("{} NOT IN ({})", column, placeholders));
+        self.where_params.extend(values.into_iter().map(Into::into));
         self
     }
 
-    // 添加 LEFT JOIN
-    pub fn left_join(mut self, table: &str, on_condition: &str) -> Self {
-        self.join_conditions.push(format!("LEFT JOIN {} ON {}", table, on_condition));
+    // 严格模式，默认关闭。开启后 in_ / not_in 收到空值列表时，validate() 和执行方法返回错误
+    pub fn strict_mode(mut self, strict: bool) -> Self {
+        self.strict = strict;
         self
     }
 
-    // 添加 RIGHT JOIN
-    pub fn right_join(mut self, table: &str, on_condition: &str) -> Self {
-        self.join_conditions.push(format!("RIGHT JOIN {} ON {}", table, on_condition));
+    // LIKE 条件
+    pub fn like(mut self, column: &str, value: &str) -> Self {
+        self.render_dialect();
+        let condition = format!("{} LIKE ?{}", column, self.like_escape());
+        let pattern = Value::from(format!("%{}%", escape_like(value)));
+        self.push_condition(condition, pattern)
+    }
+
+    // 不区分大小写的 LIKE，Postgres 使用 ILIKE，MySQL/SQLite 使用 LOWER(column) LIKE LOWER(?)
+    pub fn ilike(mut self, column: &str, value: &str) -> Self {
+        let condition = match self.render_dialect() {
+            Dialect::Postgres => format!("{} ILIKE ?", column),
+            Dialect::MySql | Dialect::Sqlite => format!("LOWER({}) LIKE LOWER(?){}", column, self.like_escape()),
+        };
+        let pattern = Value::from(format!("%{}%", escape_like(value)));
+        self.push_condition(condition, pattern)
+    }
+
+    // SQLite 的 LIKE 没有默认转义字符，需要显式指定
+    fn like_escape(&self) -> &'static str {
+        match self.dialect {
+            Dialect::Sqlite => " ESCAPE '\\'",
+            Dialect::MySql | Dialect::Postgres => "",
+        }
+    }
+
+    // 添加一个带单个绑定参数的条件
+    fn push_condition(mut self, condition: String, value: Value) -> Self {
+        self.where_conditions.push(condition);
+        self.where_params.push(value);
         self
     }
 
-    // 修改构建SQL语句方法
-    pub fn build_sql(&self, table_name: &str) -> String {
-        // 如果有自定义SQL，直接使用它
-        if let Some(custom_sql) = &self.custom_sql {
-            let mut sql = custom_sql.clone();
-            
-            // 添加WHERE条件
-            if !self.where_conditions.is_empty() {
-                if !sql.to_uppercase().contains("WHERE") {
-                    sql.push_str(" WHERE ");
-                } else {
-                    sql.push_str(" AND ");
-                }
-                sql.push_str(&self.where_conditions.join(" AND "));
+    // JSON 列中 path 指向的值等于 value，path 为以点分隔的键，如 "profile.city"
+    // path 和 value 都作为参数绑定，值按 JSON 比较，数字和字符串不会互相匹配
+    pub fn json_eq<T: Serialize>(mut self, column: &str, path: &str, value: T) -> Self {
+        let value = match serde_json::to_string(&value) {
+            Ok(value) => value,
+            Err(e) => {
+                self.set_error(format!("json_eq value for `{}` cannot be serialized: {}", column, e));
+                return self;
             }
-
-            // 添加排序
-            if !self.order_by.is_empty() {
-                sql.push_str(" ORDER BY ");
-                sql.push_str(&self.order_by.join(", "));
+        };
+        let keys: Vec<&str> = path.split('.').collect();
+        match self.render_dialect() {
+            Dialect::MySql => {
+                self.where_conditions.push(format!("JSON_EXTRACT({}, ?) = CAST(? AS JSON)", column));
+                self.where_params.push(Value::from(json_path(&keys)));
             }
-
-            // 添加分页
-            if let Some(limit) = self.limit {
-                sql.push_str(&format!(" LIMIT {}", limit));
+            Dialect::Postgres => {
+                let placeholders = vec!["?"; keys.len()].join(", ");
+                self.where_conditions.push(format!("jsonb_extract_path({}::jsonb, {}) = ?::jsonb", column, placeholders));
+                self.where_params.extend(keys.iter().map(|key| Value::from(*key)));
             }
-            if let Some(offset) = self.offset {
-                sql.push_str(&format!(" OFFSET {}", offset));
+            Dialect::Sqlite => {
+                self.where_conditions.push(format!("json_extract({}, ?) = json_extract(?, '$')", column));
+                self.where_params.push(Value::from(json_path(&keys)));
             }
-
-            return sql;
         }
+        self.where_params.push(Value::from(value));
+        self
+    }
 
-        // 常规SQL构建
-        let select = if self.select_columns.is_empty() {
-            "*".to_string()
-        } else {
-            self.select_columns.join(", ")
+    // JSON 列包含 value 对应的 JSON 文档，MySQL 使用 JSON_CONTAINS，Postgres 使用 @>
+    // SQLite 没有 JSON 包含运算，执行时返回错误
+    pub fn json_contains<T: Serialize>(mut self, column: &str, value: T) -> Self {
+        let value = match serde_json::to_string(&value) {
+            Ok(value) => value,
+            Err(e) => {
+                self.set_error(format!("json_contains value for `{}` cannot be serialized: {}", column, e));
+                return self;
+            }
+        };
+        let condition = match self.render_dialect() {
+            Dialect::MySql => format!("JSON_CONTAINS({}, ?)", column),
+            Dialect::Postgres => format!("{}::jsonb @> ?::jsonb", column),
+            Dialect::Sqlite => {
+                self.set_error("json_contains is not supported on SQLite".to_string());
+                return self;
+            }
         };
+        self.push_condition(condition, Value::from(value))
+    }
 
-        let mut sql = format!("SELECT {} FROM {}", select, table_name);
+    // 原生 WHERE 片段，片段中的 ? 按顺序绑定 params，用于 JSON 运算符、全文检索等特殊条件
+    pub fn where_raw(mut self, fragment: &str, params: Vec<Value>) -> Self {
+        self.where_conditions.push(format!("({})", fragment));
+        self.where_params.extend(params);
+        self
+    }
 
-        // 添加JOIN条件
-        if !self.join_conditions.is_empty() {
-            sql.push_str(" ");
-            sql.push_str(&self.join_conditions.join(" "));
+    // 合并可复用的 Condition，条件和参数按顺序追加到当前 wrapper 的 WHERE 条件之后
+    pub fn apply(mut self, condition: &Condition) -> Self {
+        let inner = &condition.inner;
+        self.inherit_dialect(inner);
+        if let Some(error) = &inner.error {
+            self.set_error(error.clone());
         }
+        self.empty_value_sets.extend(inner.empty_value_sets.iter().cloned());
+        self.where_conditions.extend(inner.where_conditions.iter().cloned());
+        self.where_params.extend(inner.where_params.iter().cloned());
+        self
+    }
 
-        if !self.where_conditions.is_empty() {
-            sql.push_str(" WHERE ");
-            sql.push_str(&self.where_conditions.join(" AND "));
-        }
+    // 嵌套条件组，闭包中的条件以 AND 连接后加括号，整体与外层条件 AND 连接
+    // 例: .and_group(|w| w.eq("a", 1).eq("b", 2)) 生成 AND (a = ? AND b = ?)
+    pub fn and_group<F>(self, f: F) -> Self
+    where
+        F: FnOnce(QueryWrapper) -> QueryWrapper,
+    {
+        self.push_group(f, " AND ")
+    }
 
-        if !self.order_by.is_empty() {
-            sql.push_str(" ORDER BY ");
-            sql.push_str(&self.order_by.join(", "));
-        }
+    // 嵌套条件组，闭包中的条件以 OR 连接后加括号，整体与外层条件 AND 连接，可与 and_group 任意嵌套
+    // 例: .or_group(|w| w.eq("a", 1).and_group(|w| w.eq("b", 2).eq("c", 3))) 生成 AND (a = ? OR (b = ? AND c = ?))
+    pub fn or_group<F>(self, f: F) -> Self
+    where
+        F: FnOnce(QueryWrapper) -> QueryWrapper,
+    {
+        self.push_group(f, " OR ")
+    }
 
-        if let Some(limit) = self.limit {
-            sql.push_str(&format!(" LIMIT {}", limit));
+    // 用闭包构建子条件并按 connector 连接，子条件的参数按顺序追加，空条件组忽略
+    fn push_group<F>(mut self, f: F, connector: &str) -> Self
+    where
+        F: FnOnce(QueryWrapper) -> QueryWrapper,
+    {
+        let group = f(QueryWrapper::new().dialect(self.dialect));
+        self.inherit_dialect(&group);
+        if let Some(error) = group.error {
+            self.set_error(error);
         }
+        self.empty_value_sets.extend(group.empty_value_sets);
+        if group.where_conditions.is_empty() {
+            return self;
+        }
+        self.where_conditions.push(format!("({})", group.where_conditions.join(connector)));
+        self.where_params.extend(group.where_params);
+        self
+    }
+
+    // 指定查询列，替换已有的查询列 (包括 select_count_as 追加的子查询列及其参数)
+    pub fn select(mut self, columns: Vec<&str>) -> Self {
+        self.select_columns = columns.into_iter().map(String::from).collect();
+        self.select_params.clear();
+        self
+    }
 
-        if let Some(offset) = self.offset {
-            sql.push_str(&format!(" OFFSET {}", offset));
+    // Postgres 的 SELECT DISTINCT ON (columns)，每组只保留一行，通常配合以这些列开头的 order_by 取每组最新的一行
+    // 其它方言不支持，执行时返回错误
+    pub fn distinct_on(mut self, columns: Vec<&str>) -> Self {
+        self.distinct_on = columns.into_iter().map(String::from).collect();
+        self
+    }
+
+    // 追加一个子查询计数列 (SELECT COUNT(*) FROM ...) AS alias，需在 select 之后调用
+    // 关联条件可以在子查询中用 where_raw 指定，如 where_raw("o.user_id = u.id", vec![])
+    pub fn select_count_as(mut self, sub: &QueryWrapper, sub_table: &str, alias: &str) -> Self {
+        if self.select_columns.is_empty() {
+            self.select_columns.push("*".to_string());
         }
+        self.inherit_errors(sub);
+        self.select_columns.push(format!("({}) AS {}", sub.build_count_sql(sub_table), alias));
+        self.select_params.extend(sub.build_count_params());
+        self
+    }
 
-        sql
+    // 字符串聚合列，MySQL/SQLite 使用 GROUP_CONCAT，Postgres 使用 STRING_AGG
+    // 追加到查询列中，需在 select 之后调用，通常配合 group_by 使用
+    pub fn group_concat(mut self, column: &str, separator: &str, alias: &str) -> Self {
+        let expr = match self.render_dialect() {
+            Dialect::MySql => format!("GROUP_CONCAT({} SEPARATOR {})", column, self.quote(separator)),
+            Dialect::Postgres => format!("STRING_AGG({}, {})", column, self.quote(separator)),
+            Dialect::Sqlite => format!("GROUP_CONCAT({}, {})", column, self.quote(separator)),
+        };
+        self.select_columns.push(format!("{} AS {}", expr, alias));
+        self
     }
 
-    // 执行查询
-    pub async fn query<T>(&self, rb: &RBatis, table_name: &str) -> Result<Vec<T>, Error>
-    where
-        T: Serialize + for<'de> serde::Deserialize<'de>,
-    {
-        let sql = self.build_sql(table_name);
-        rb.query_decode(&sql, vec![]).await
+    // 分组求和: SELECT group_column, SUM(sum_column) AS alias ... GROUP BY group_column
+    // 替换已有的查询列，结果用 query::<T> 解码为结构体，或 query_tuple::<(String, f64)> 解码为元组
+    pub fn sum_group(mut self, group_column: &str, sum_column: &str, alias: &str) -> Self {
+        self.select_columns = vec![group_column.to_string(), format!("SUM({}) AS {}", sum_column, alias)];
+        self.select_params.clear();
+        self.group_by(vec![group_column])
     }
 
-    // 执行查询
-    pub async fn get_one<T>(&self, rb: &RBatis, table_name: &str) -> Result<Option<T>, Error>
-    where
-        T: Serialize + for<'de> serde::Deserialize<'de>,
-    {
-        let sql = self.build_sql(table_name);
-        rb.query_decode::<Option<T>>(&sql, vec![]).await
+    // 分组
+    pub fn group_by(mut self, columns: Vec<&str>) -> Self {
+        self.group_by.extend(columns.into_iter().map(String::from));
+        self
     }
 
-    // 执行删除
-    pub async fn delete(self, rb: &RBatis, table_name: &str) -> Result<u64, Error> {
-        let delete_sql = format!("delete from {}", table_name);
-        let sql = self.custom_sql(&delete_sql)
-            .build_sql(table_name);
-        Ok(rb.exec(&sql, vec![]).await?.rows_affected)
+    // 分组后的过滤条件，片段中的 ? 按顺序绑定 params，多次调用以 AND 连接
+    pub fn having(mut self, fragment: &str, params: Vec<Value>) -> Self {
+        self.having.push(format!("({})", fragment));
+        self.having_params.extend(params);
+        self
     }
 
-    // 修改分页方法
-    pub async fn page<T>(&self, rb: &RBatis, table_name: &str, page_no: u64, page_size: u64) -> Result<Page<T>, Error>
-    where
-        T: Serialize + for<'de> serde::Deserialize<'de>,
-    {
-        // 1. 先查询总记录数
-        let count_sql = self.build_count_sql(table_name);
-        let total: u64 = rb.query_decode(&count_sql, vec![]).await?;
+    // 查找重复值: SELECT column, COUNT(*) AS dup_count ... GROUP BY column HAVING COUNT(*) > 1
+    // 可以继续添加条件和排序，结果用 query / query_json 获取
+    pub fn find_duplicates(mut self, column: &str) -> Self {
+        self.select_columns = vec![column.to_string(), "COUNT(*) AS dup_count".to_string()];
+        self.select_params.clear();
+        self.group_by(vec![column]).having("COUNT(*) > 1", vec![])
+    }
 
-        // 2. 如果有数据，再查询分页数据
-        if total > 0 {
-            // 设置分页参数
-            let offset = (page_no - 1) * page_size;
-            let mut wrapper = self.clone();
-            wrapper.limit(page_size);  // 现在这些方法返回 &mut Self
-            wrapper.offset(offset);    // 可以分开调用
-            
-            // 查询分页数据
-            let records: Vec<T> = wrapper.query(rb, table_name).await?;
-            
-            Ok(Page::new(records, total, page_no, page_size))
-        } else {
-            // 没有数据时返回空页
-            Ok(Page::new(vec![], 0, page_no, page_size))
+    // 设置允许排序的列，排序方法使用了不在列表中的列时，validate() 和执行方法返回错误
+    // 用于排序字段来自请求参数的场景，与排序方法的调用顺序无关
+    pub fn sortable_columns(mut self, columns: &[&str]) -> Self {
+        self.sortable_columns = Some(columns.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    // 带小计的分组，MySQL 生成 GROUP BY a, b WITH ROLLUP，Postgres 生成 GROUP BY ROLLUP(a, b)，SQLite 不支持
+    // ROLLUP 会额外产生小计和总计行，分页统计的总数包含这些行
+    pub fn group_by_rollup(mut self, columns: Vec<&str>) -> Self {
+        self.rollup = true;
+        self.group_by(columns)
+    }
+
+    // 排序
+    pub fn order_by(mut self, column: &str, asc: bool) -> Self {
+        self.order_columns.push(column.to_string());
+        let order = if asc { "ASC" } else { "DESC" };
+        self.order_by.push(format!("{} {}", column, order));
+        self
+    }
+
+    // 只在 column 属于 allowed 时排序，否则不添加排序并记录错误，由 validate() 和执行方法返回
+    // 只对本次调用生效，需要限制所有排序方法时使用 sortable_columns
+    pub fn order_by_checked(mut self, column: &str, asc: bool, allowed: &[&str]) -> Self {
+        if !allowed.contains(&column) {
+            self.set_error(format!("order by column `{}` is not allowed", column));
+            return self;
         }
+        self.order_by(column, asc)
     }
 
-    // 修改构建统计SQL方法
-    fn build_count_sql(&self, table_name: &str) -> String {
-        if let Some(custom_sql) = &self.custom_sql {
-            // 将 WHERE 条件放入子查询内部
-            let mut inner_sql = custom_sql.clone();
-            
-            if !self.where_conditions.is_empty() {
-                if !inner_sql.to_uppercase().contains("WHERE") {
-                    inner_sql.push_str(" WHERE ");
-                } else {
-                    inner_sql.push_str(" AND ");
-                }
-                inner_sql.push_str(&self.where_conditions.join(" AND "));
-            }
+    // 升序排序
+    pub fn order_by_asc(self, column: &str) -> Self {
+        self.order_by(column, true)
+    }
 
-            // 包装成计数查询
-            format!("SELECT COUNT(*) FROM ({}) as t", inner_sql)
-        } else {
-            let mut sql = format!("SELECT COUNT(*) FROM {}", table_name);
+    // 降序排序
+    pub fn order_by_desc(self, column: &str) -> Self {
+        self.order_by(column, false)
+    }
 
-            // 添加JOIN条件
-            if !self.join_conditions.is_empty() {
-                sql.push_str(" ");
-                sql.push_str(&self.join_conditions.join(" "));
-            }
+    // 按 Order 指定方向排序，调用处比 order_by 的 bool 参数更清晰
+    pub fn order_by_dir(self, column: &str, order: Order) -> Self {
+        self.order_by(column, order.is_asc())
+    }
 
-            if !self.where_conditions.is_empty() {
-                sql.push_str(" WHERE ");
-                sql.push_str(&self.where_conditions.join(" AND "));
-            }
+    // 按 Order 指定方向排序并指定 NULL 值的位置，规则同 order_by_nulls
+    pub fn order_by_dir_nulls(self, column: &str, order: Order, nulls: NullsOrder) -> Self {
+        self.order_by_nulls(column, order.is_asc(), nulls)
+    }
+
+    // 一次添加多个排序项，按传入顺序追加
+    pub fn order_by_multi(self, columns: &[(&str, bool)]) -> Self {
+        columns.iter().fold(self, |wrapper, (column, asc)| wrapper.order_by(column, *asc))
+    }
 
-            sql
+    // 排序并指定 NULL 值的位置，Postgres/SQLite 生成 NULLS FIRST/LAST，
+    // MySQL 不支持该语法，使用 ISNULL(column) 作为额外的排序项模拟
+    pub fn order_by_nulls(mut self, column: &str, asc: bool, nulls: NullsOrder) -> Self {
+        self.order_columns.push(column.to_string());
+        let order = if asc { "ASC" } else { "DESC" };
+        match self.render_dialect() {
+            Dialect::MySql => {
+                let nulls = match nulls {
+                    NullsOrder::First => "DESC",
+                    NullsOrder::Last => "ASC",
+                };
+                self.order_by.push(format!("ISNULL({}) {}", column, nulls));
+                self.order_by.push(format!("{} {}", column, order));
+            }
+            Dialect::Postgres | Dialect::Sqlite => {
+                let nulls = match nulls {
+                    NullsOrder::First => "NULLS FIRST",
+                    NullsOrder::Last => "NULLS LAST",
+                };
+                self.order_by.push(format!("{} {} {}", column, order, nulls));
+            }
         }
+        self
+    }
+
+    // 遇到死锁、连接中断等临时性错误时重试，最多重试 attempts 次，等待时间从 backoff 开始每次翻倍
+    // 语法错误、约束冲突等错误不重试，delete / exec 等更新语句需要同时调用 idempotent()
+    pub fn with_retry(self, attempts: u32, backoff: Duration) -> Self {
+        self.retry(RetryPolicy::new(attempts.saturating_add(1), backoff))
+    }
+
+    // 按重试策略重试查询，delete / exec 等更新语句只有标记 idempotent 后才会重试
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    // 标记更新语句可以安全地重复执行 (如按主键删除)，开启重试时 delete / exec 等也会重试
+    pub fn idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
     }
-}
\ No newline at end of file
+
+    // 单条语句的执行超时，超时后放弃等待并返回包含 SQL 的错误，可用 is_timeout_error 判断
+    // 开启重试时每次尝试单独计时，超时错误不会重试
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // 日志中隐藏参数值，只输出参数个数，用于包含密码、手机号等敏感数据的查询 (需要开启 log feature)
+    pub fn redact_params(mut self, redact: bool) -> Self {
+        self.redact_params = redact;
+        self
+    }
+
+    // 查询时锁定返回的行并跳过其它事务已锁定的行，用于多个消费者从表中领取任务
+    // 需要在事务中执行，支持 Postgres 和 MySQL 8.0+，SQLite 执行时返回错误
+    // 只作用于查询语句，delete / exec 不追加；不能与 union、group_by、page_window 一起使用
+    pub fn for_update_skip_locked(mut self) -> Self {
+        self.skip_locked = true;
+        self
+    }
+
+    // 默认排序，只在分页 (limit / offset / page) 且没有其它排序时生效，保证分页结果稳定
+    pub fn default_order(mut self, column: &str, asc: bool) -> Self {
+        let order = if asc { "ASC" } else { "DESC" };
+        self.default_order = Some(format!("{} {}", column, order));
+        self
+    }
+
+    // 分页时必须有排序，开启后分页查询既没有 order_by 也没有 default_order 时返回错误
+    // 没有排序的分页结果顺序不确定，翻页时可能出现重复或遗漏的记录
+    pub fn require_order(mut self, require_order: bool) -> Self {
+        self.require_order = require_order;
+        self
+    }
+
+    // 深分页优化，page() 先按条件和排序只查询主键 (可走覆盖索引)，再用 pk IN (...) 查询整行
+    // 适用于 OFFSET 很大的导出场景，自定义SQL、UNION、分组查询不使用该优化
+    pub fn optimize_deep_offset(mut self, pk_column: &str) -> Self {
+        self.deep_offset_pk = Some(pk_column.to_string());
+        self
+    }
+
+    // 按表达式排序，表达式原样拼接到 ORDER BY 中，只能传入可信的内容，不要直接使用用户输入
+    // 设置了 sortable_columns 时，表达式必须与允许的列完全一致
+    pub fn order_by_raw(mut self, expr: &str) -> Self {
+        self.order_columns.push(expr.to_string());
+        self.order_by.push(expr.to_string());
+        self
+    }
+
+    // 随机排序，配合 limit 随机抽样，MySQL 使用 RAND()，Postgres/SQLite 使用 RANDOM()
+    pub fn order_by_random(mut self) -> Self {
+        let expr = match self.render_dialect() {
+            Dialect::MySql => "RAND()",
+            Dialect::Postgres | Dialect::Sqlite => "RANDOM()",
+        };
+        self.order_by.push(expr.to_string());
+        self
+    }
+
+    // 按指定值列表自定义排序，MySQL 使用 FIELD()，其它方言使用 CASE 表达式
+    pub fn order_by_field<T: ToString>(self, column: &str, values: &[T]) -> Self {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        self.push_value_order(column, &values, None)
+    }
+
+    // 按值列表的顺序排序，常用于按 IN 条件传入 id 的顺序返回结果，asc 为 false 时倒序
+    pub fn order_by_values<T: ToString>(self, column: &str, values: Vec<T>, asc: bool) -> Self {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        let order = if asc { "ASC" } else { "DESC" };
+        self.push_value_order(column, &values, Some(order))
+    }
+
+    // 值列表为空时不添加排序
+    fn push_value_order(mut self, column: &str, values: &[String], order: Option<&str>) -> Self {
+        if values.is_empty() {
+            return self;
+        }
+        self.order_columns.push(column.to_string());
+        let dialect = self.render_dialect();
+        let values: Vec<String> = values.iter().map(|v| self.quote(v)).collect();
+        let mut expr = match dialect {
+            Dialect::MySql => format!("FIELD({}, {})", column, values.join(", ")),
+            _ => {
+                let mut expr = format!("CASE {}", column);
+                for (i, value) in values.iter().enumerate() {
+                    expr.push_str(&format!(" WHEN {} THEN {}", value, i));
+                }
+                expr.push_str(&format!(" ELSE {} END", values.len()));
+                expr
+            }
+        };
+        if let Some(order) = order {
+            expr.push(' ');
+            expr.push_str(order);
+        }
+        self.order_by.push(expr);
+        self
+    }
+
+    // 限制记录数
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    // 偏移量
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    // 查询记录数上限，limit 超过上限时按上限截断，未设置 limit 的查询自动追加上限，删除语句不受影响
+    // 分页方法的 page_size 超过上限时按上限处理，offset 和 Page 中的 page_size 都按截断后的值计算
+    pub fn max_limit(mut self, max_limit: u64) -> Self {
+        self.max_limit = Some(max_limit);
+        self
+    }
+
+    // 设置 page() 中页码超出总页数时的处理方式，默认返回空数据
+    pub fn page_overflow(mut self, page_overflow: PageOverflow) -> Self {
+        self.page_overflow = page_overflow;
+        self
+    }
+
+    // page() 在查询列中追加 COUNT(*) OVER()，一次查询同时得到数据和总数 (MySQL 8.0+、Postgres、SQLite 3.25+)
+    // 自定义SQL、UNION、DISTINCT ON 或请求页没有数据时仍使用单独的统计查询
+    pub fn page_window(mut self, window_count: bool) -> Self {
+        self.window_count = window_count;
+        self
+    }
+
+    // page() 同时执行统计和数据查询，减少一次往返等待，默认关闭
+    // 开启后即使总数为 0 或页码超出总页数也会执行数据查询，结果直接丢弃
+    pub fn concurrent_page(mut self, concurrent: bool) -> Self {
+        self.concurrent_page = concurrent;
+        self
+    }
+
+    // 设置 page() 是否查询总记录数，默认查询。关闭后多取一条记录判断是否有下一页，total 和 pages 为 0
+    // 适用于不需要总数的无限滚动列表
+    pub fn search_count(mut self, search_count: bool) -> Self {
+        self.skip_count = !search_count;
+        self
+    }
+
+    // 自定义统计SQL，count() 和 page() 原样使用该语句统计总数，适用于有更廉价等价统计语句的复杂查询
+    // 语句必须只返回一行一个数值列，否则 count() 返回错误
+    pub fn count_sql(mut self, sql: &str) -> Self {
+        self.count_sql_override = Some(sql.to_string());
+        self.count_sql_where = false;
+        self
+    }
+
+    // 自定义统计SQL，并追加当前 wrapper 的 WHERE 条件
+    pub fn count_sql_with_conditions(mut self, sql: &str) -> Self {
+        self.count_sql_override = Some(sql.to_string());
+        self.count_sql_where = true;
+        self
+    }
+
+    // 同时设置偏移量和记录数，避免 limit / offset 调用顺序混淆
+    pub fn limit_offset(mut self, offset: u64, count: u64) -> Self {
+        self.offset = Some(offset);
+        self.limit = Some(count);
+        self
+    }
+
+    // 添加自定义SQL方法
+    pub fn custom_sql(mut self, sql: &str) -> Self {
+        self.custom_sql = Some(sql.to_string());
+        self.custom_params.clear();
+        self
+    }
+
+    // 带绑定参数的自定义SQL，SQL 中的 ? 按顺序绑定 params，之后添加的条件参数排在其后
+    pub fn custom_sql_with(mut self, sql: &str, params: Vec<Value>) -> Self {
+        self.custom_sql = Some(sql.to_string());
+        self.custom_params = params;
+        self
+    }
+
+    // 添加公共表表达式 WITH name AS (...)，可多次调用，后续的 JOIN 和条件可以把 name 当作表引用
+    pub fn with(mut self, name: &str, sub: &QueryWrapper, sub_table: &str) -> Self {
+        self.inherit_errors(sub);
+        self.ctes.push(format!("{} AS ({})", name, sub.build_sql(sub_table)));
+        self.cte_params.extend(sub.build_params());
+        self
+    }
+
+    // 添加递归公共表表达式 WITH RECURSIVE name AS (anchor UNION ALL recursive_sql)，
+    // 并将当前查询的来源设置为 name，recursive_sql 中可以 JOIN name 引用上一层结果
+    pub fn with_recursive(mut self, name: &str, anchor: &QueryWrapper, anchor_table: &str, recursive_sql: &str) -> Self {
+        self.inherit_errors(anchor);
+        self.ctes.push(format!("{} AS ({} UNION ALL {})", name, anchor.build_sql(anchor_table), recursive_sql));
+        self.cte_params.extend(anchor.build_params());
+        self.recursive = true;
+        self.from_source = Some(name.to_string());
+        self
+    }
+
+    // 与 with_recursive 相同，递归部分也由 wrapper 构建，可以带绑定参数 (如限制层级)
+    // 参数顺序为 anchor、step、当前查询
+    pub fn with_recursive_query(mut self, name: &str, anchor: &QueryWrapper, anchor_table: &str, step: &QueryWrapper, step_table: &str) -> Self {
+        self.inherit_errors(anchor);
+        self.inherit_errors(step);
+        self.ctes.push(format!("{} AS ({} UNION ALL {})", name, anchor.build_sql(anchor_table), step.build_sql(step_table)));
+        self.cte_params.extend(anchor.build_params());
+        self.cte_params.extend(step.build_params());
+        self.recursive = true;
+        self.from_source = Some(name.to_string());
+        self
+    }
+
+    // 与另一个 wrapper 的结果合并去重 (UNION)，当前 wrapper 的排序和分页作用于合并后的结果
+    pub fn union(self, other: QueryWrapper, other_table: &str) -> Self {
+        self.push_union("UNION", other, other_table)
+    }
+
+    // 与另一个 wrapper 的结果合并不去重 (UNION ALL)
+    pub fn union_all(self, other: QueryWrapper, other_table: &str) -> Self {
+        self.push_union("UNION ALL", other, other_table)
+    }
+
+    fn push_union(mut self, union: &str, other: QueryWrapper, other_table: &str) -> Self {
+        // 双方都显式指定查询列时，列数必须一致
+        if !self.select_columns.is_empty()
+            && !other.select_columns.is_empty()
+            && self.select_columns.len() != other.select_columns.len()
+        {
+            self.set_error(format!(
+                "{} branches select a different number of columns: {} vs {}",
+                union,
+                self.select_columns.len(),
+                other.select_columns.len()
+            ));
+        }
+        self.inherit_errors(&other);
+        if !other.order_by.is_empty() || other.effective_limit().is_some() || other.offset.is_some() {
+            self.union_branch_paged = true;
+        }
+        self.unions.push((union.to_string(), other.build_sql(other_table)));
+        self.union_params.extend(other.build_params());
+        self
+    }
+
+    // 合并按其它 wrapper 的方言生成的片段，方言不一致时记录错误
+    fn inherit_dialect(&mut self, sub: &QueryWrapper) {
+        if !sub.dialect_rendered {
+            return;
+        }
+        if sub.dialect != self.dialect {
+            self.set_error(format!(
+                "{:?}-specific SQL from a nested wrapper cannot be used in a {:?} query",
+                sub.dialect, self.dialect
+            ));
+        }
+        self.dialect_rendered = true;
+    }
+
+    // 子查询 wrapper 构建时记录的错误和空值集合合并到当前 wrapper，由 validate() 返回
+    // 子查询中出错的条件已被跳过，不合并会在缺少该条件的情况下执行
+    fn inherit_errors(&mut self, sub: &QueryWrapper) {
+        self.inherit_dialect(sub);
+        if let Err(e) = sub.validate() {
+            self.set_error(e.to_string());
+        }
+        self.empty_value_sets.extend(sub.empty_value_sets.iter().cloned());
+    }
+
+    // 添加 INNER JOIN
+    pub fn inner_join(mut self, table: &str, on_condition: &str) -> Self {
+        self.join_conditions.push(format!("INNER JOIN {} ON {}", table, on_condition));
+        self
+    }
+
+    // 添加 LEFT JOIN
+    pub fn left_join(mut self, table: &str, on_condition: &str) -> Self {
+        self.join_conditions.push(format!("LEFT JOIN {} ON {}", table, on_condition));
+        self
+    }
+
+    // 添加 RIGHT JOIN
+    pub fn right_join(mut self, table: &str, on_condition: &str) -> Self {
+        self.join_conditions.push(format!("RIGHT JOIN {} ON {}", table, on_condition));
+        self
+    }
+
+    // 设置主表别名，生成 FROM table AS alias，JOIN 和条件中可以使用该别名
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.alias = Some(alias.to_string());
+        self
+    }
+
+    // 以子查询 (派生表) 作为 FROM 来源，设置后忽略执行方法传入的表名
+    pub fn from_subquery(mut self, inner: &QueryWrapper, inner_table: &str, alias: &str) -> Self {
+        self.inherit_errors(inner);
+        self.from_source = Some(format!("({}) {}", inner.build_sql(inner_table), alias));
+        self.from_params = inner.build_params();
+        self
+    }
+
+    // 添加 FULL JOIN (MySQL 不支持)
+    pub fn full_join(mut self, table: &str, on_condition: &str) -> Self {
+        self.join_conditions.push(format!("FULL JOIN {} ON {}", table, on_condition));
+        self
+    }
+
+    // 添加 CROSS JOIN
+    pub fn cross_join(mut self, table: &str) -> Self {
+        self.join_conditions.push(format!("CROSS JOIN {}", table));
+        self
+    }
+
+    // 添加带别名的 INNER JOIN，ON 条件中的 ? 按顺序绑定 params
+    pub fn inner_join_as(self, table: &str, alias: &str, on_condition: &str, params: Vec<Value>) -> Self {
+        self.join_as("INNER JOIN", table, alias, on_condition, params)
+    }
+
+    // 添加带别名的 LEFT JOIN
+    pub fn left_join_as(self, table: &str, alias: &str, on_condition: &str, params: Vec<Value>) -> Self {
+        self.join_as("LEFT JOIN", table, alias, on_condition, params)
+    }
+
+    // 添加带别名的 RIGHT JOIN
+    pub fn right_join_as(self, table: &str, alias: &str, on_condition: &str, params: Vec<Value>) -> Self {
+        self.join_as("RIGHT JOIN", table, alias, on_condition, params)
+    }
+
+    // 添加带别名的 FULL JOIN (MySQL 不支持)
+    pub fn full_join_as(self, table: &str, alias: &str, on_condition: &str, params: Vec<Value>) -> Self {
+        self.join_as("FULL JOIN", table, alias, on_condition, params)
+    }
+
+    fn join_as(mut self, join: &str, table: &str, alias: &str, on_condition: &str, params: Vec<Value>) -> Self {
+        self.join_conditions.push(format!("{} {} AS {} ON {}", join, table, alias, on_condition));
+        self.join_params.extend(params);
+        self
+    }
+
+    // 添加子查询 INNER JOIN，子查询由另一个 wrapper 构建
+    pub fn inner_join_subquery(self, sub: &QueryWrapper, sub_table: &str, alias: &str, on_condition: &str) -> Self {
+        self.join_subquery("INNER JOIN", sub, sub_table, alias, on_condition)
+    }
+
+    // 添加子查询 LEFT JOIN
+    pub fn left_join_subquery(self, sub: &QueryWrapper, sub_table: &str, alias: &str, on_condition: &str) -> Self {
+        self.join_subquery("LEFT JOIN", sub, sub_table, alias, on_condition)
+    }
+
+    // 添加子查询 RIGHT JOIN
+    pub fn right_join_subquery(self, sub: &QueryWrapper, sub_table: &str, alias: &str, on_condition: &str) -> Self {
+        self.join_subquery("RIGHT JOIN", sub, sub_table, alias, on_condition)
+    }
+
+    // 子查询保留自身的排序和分页，整体放入括号中
+    fn join_subquery(mut self, join: &str, sub: &QueryWrapper, sub_table: &str, alias: &str, on_condition: &str) -> Self {
+        self.inherit_errors(sub);
+        let sub_sql = sub.build_sql(sub_table);
+        self.join_conditions.push(format!("{} ({}) {} ON {}", join, sub_sql, alias, on_condition));
+        self.join_params.extend(sub.build_params());
+        self
+    }
+
+    // 修改构建SQL语句方法
+    pub fn build_sql(&self, table_name: &str) -> String {
+        self.build_sql_with_page(table_name, self.effective_limit(), self.offset)
+    }
+
+    // 按 max_limit 截断后的 limit，没有设置 limit 时使用 max_limit
+    fn effective_limit(&self) -> Option<u64> {
+        match self.limit {
+            Some(limit) => Some(self.cap_limit(limit)),
+            None => self.max_limit,
+        }
+    }
+
+    // 按 max_limit 截断 limit 或 page_size
+    fn cap_limit(&self, limit: u64) -> u64 {
+        self.max_limit.map_or(limit, |max_limit| limit.min(max_limit))
+    }
+
+    // 使用指定的 limit / offset 构建查询语句，分页查询不需要为了设置分页参数复制整个 wrapper
+    fn build_sql_with_page(&self, table_name: &str, limit: Option<u64>, offset: Option<u64>) -> String {
+        let select = self.build_select(table_name, limit, offset);
+        if self.ctes.is_empty() {
+            return select;
+        }
+        let mut sql = self.build_with();
+        sql.push_str(&select);
+        sql
+    }
+
+    // WITH 前缀，没有公共表表达式时为空
+    fn build_with(&self) -> String {
+        if self.ctes.is_empty() {
+            String::new()
+        } else {
+            let with = if self.recursive { "WITH RECURSIVE" } else { "WITH" };
+            format!("{} {} ", with, self.ctes.join(", "))
+        }
+    }
+
+    fn build_select(&self, table_name: &str, limit: Option<u64>, offset: Option<u64>) -> String {
+        let mut sql = self.build_compound(table_name, &self.select_list(), false);
+
+        let paginated = limit.is_some() || offset.is_some();
+        if !self.order_by.is_empty() {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(&self.order_by.join(", "));
+        } else if let (Some(default_order), true) = (&self.default_order, paginated) {
+            sql.push_str(" ORDER BY ");
+            sql.push_str(default_order);
+        }
+
+        self.push_limit(&mut sql, limit, offset);
+        if self.skip_locked {
+            sql.push_str(" FOR UPDATE SKIP LOCKED");
+        }
+        sql
+    }
+
+    // 是否设置了分页
+    fn is_paginated(&self) -> bool {
+        self.limit.is_some() || self.offset.is_some()
+    }
+
+    // 检查分页查询是否有排序
+    fn check_order(&self) -> Result<(), Error> {
+        if self.require_order && self.order_by.is_empty() && self.default_order.is_none() {
+            return Err(Error::from("paginated query requires an ORDER BY (use order_by or default_order)"));
+        }
+        Ok(())
+    }
+
+    // 添加分页，MySQL 同时有 limit 和 offset 时使用 LIMIT offset, count 形式
+    // limit 已由调用方按 max_limit 处理，这里原样输出
+    fn push_limit(&self, sql: &mut String, limit: Option<u64>, offset: Option<u64>) {
+        match (limit, offset, self.dialect) {
+            (Some(limit), Some(offset), Dialect::MySql) => {
+                sql.push_str(&format!(" LIMIT {}, {}", offset, limit));
+            }
+            // 只有 offset 时，MySQL 和 SQLite 不接受单独的 OFFSET，使用文档中推荐的最大值 / -1 表示不限制
+            (None, Some(offset), Dialect::MySql) => {
+                sql.push_str(&format!(" LIMIT {}, {}", offset, u64::MAX));
+            }
+            (None, Some(offset), Dialect::Sqlite) => {
+                sql.push_str(&format!(" LIMIT -1 OFFSET {}", offset));
+            }
+            (limit, offset, _) => {
+                if let Some(limit) = limit {
+                    sql.push_str(&format!(" LIMIT {}", limit));
+                }
+                if let Some(offset) = offset {
+                    sql.push_str(&format!(" OFFSET {}", offset));
+                }
+            }
+        }
+    }
+
+    // 查询列，未指定时为 *
+    fn select_list(&self) -> String {
+        let columns = if self.select_columns.is_empty() {
+            "*".to_string()
+        } else {
+            self.select_columns.join(", ")
+        };
+        if self.distinct_on.is_empty() {
+            columns
+        } else {
+            format!("DISTINCT ON ({}) {}", self.distinct_on.join(", "), columns)
+        }
+    }
+
+    // 查询主体、分组以及 UNION 分支，不含排序和分页
+    // for_count 为 true 时去掉自定义SQL末尾的 ORDER BY，统计时排序没有意义
+    fn build_compound(&self, table_name: &str, select: &str, for_count: bool) -> String {
+        let mut sql = self.build_filter(table_name, select, for_count);
+        self.push_group_by(&mut sql);
+
+        // 有 UNION 时每个分支都放入括号中，SQLite 不接受带括号的分支
+        if !self.unions.is_empty() {
+            let parenthesize = self.dialect != Dialect::Sqlite;
+            if parenthesize {
+                sql = format!("({})", sql);
+            }
+            for (union, branch) in &self.unions {
+                if parenthesize {
+                    sql.push_str(&format!(" {} ({})", union, branch));
+                } else {
+                    sql.push_str(&format!(" {} {}", union, branch));
+                }
+            }
+        }
+
+        sql
+    }
+
+    // 查询主体与 WHERE 条件，数据查询和统计查询共用，保证两者的过滤条件始终一致
+    fn build_filter(&self, table_name: &str, select: &str, for_count: bool) -> String {
+        // 如果有自定义SQL，直接使用它，select 不生效
+        let mut sql = match &self.custom_sql {
+            Some(custom_sql) if for_count => strip_order_by(custom_sql).to_string(),
+            Some(custom_sql) => custom_sql.clone(),
+            None => {
+                // 预估语句长度，减少拼接条件、排序时的重新分配
+                let mut sql = String::with_capacity(self.estimated_sql_len(table_name, select));
+                sql.push_str("SELECT ");
+                sql.push_str(select);
+                sql.push_str(" FROM ");
+                sql.push_str(&self.table_source(table_name));
+
+                // 添加JOIN条件
+                self.push_joins(&mut sql, table_name);
+
+                sql
+            }
+        };
+
+        self.push_where(&mut sql);
+        sql
+    }
+
+    // 语句长度的粗略估计，只用于预分配
+    fn estimated_sql_len(&self, table_name: &str, select: &str) -> usize {
+        let parts = |items: &[String]| items.iter().map(|item| item.len() + 5).sum::<usize>();
+        64 + select.len()
+            + table_name.len()
+            + parts(&self.join_conditions)
+            + parts(&self.where_conditions)
+            + parts(&self.group_by)
+            + parts(&self.having)
+            + parts(&self.order_by)
+    }
+
+    // 添加WHERE条件，自定义SQL中已有 WHERE 时使用 AND 连接
+    fn push_where(&self, sql: &mut String) {
+        self.append_where(sql, self.custom_sql.is_some());
+    }
+
+    // may_have_where 表示 sql 由用户提供，可能已经包含 WHERE
+    fn append_where(&self, sql: &mut String, may_have_where: bool) {
+        if self.where_conditions.is_empty() {
+            return;
+        }
+        if may_have_where && sql.to_uppercase().contains("WHERE") {
+            sql.push_str(" AND ");
+        } else {
+            sql.push_str(" WHERE ");
+        }
+        sql.push_str(&self.where_conditions.join(" AND "));
+    }
+
+    // 添加分组
+    fn push_group_by(&self, sql: &mut String) {
+        if !self.group_by.is_empty() {
+            self.push_group_columns(sql);
+        }
+        if !self.having.is_empty() {
+            sql.push_str(" HAVING ");
+            sql.push_str(&self.having.join(" AND "));
+        }
+    }
+
+    fn push_group_columns(&self, sql: &mut String) {
+        let columns = self.group_by.join(", ");
+        match (self.rollup, self.dialect) {
+            (true, Dialect::MySql) => sql.push_str(&format!(" GROUP BY {} WITH ROLLUP", columns)),
+            (true, _) => sql.push_str(&format!(" GROUP BY ROLLUP({})", columns)),
+            (false, _) => sql.push_str(&format!(" GROUP BY {}", columns)),
+        }
+    }
+
+    // 按 SQL 中占位符出现的顺序收集绑定参数，与 build_sql / build_count_sql 对应
+    pub fn build_params(&self) -> Vec<Value> {
+        self.collect_params(true)
+    }
+
+    // 统计SQL的绑定参数，常规统计使用 COUNT(*) 代替查询列，没有查询列中的参数
+    pub fn build_count_params(&self) -> Vec<Value> {
+        if self.count_sql_override.is_some() {
+            return if self.count_sql_where { self.where_params.clone() } else { vec![] };
+        }
+        self.collect_params(self.wraps_count())
+    }
+
+    // 统计时是否需要把查询包装为子查询，自定义SQL、UNION、分组和 DISTINCT ON 不能直接替换为 COUNT(*)
+    // 分组查询统计的是分组数而不是原始行数
+    fn wraps_count(&self) -> bool {
+        self.custom_sql.is_some()
+            || !self.unions.is_empty()
+            || !self.group_by.is_empty()
+            || !self.having.is_empty()
+            || !self.distinct_on.is_empty()
+    }
+
+    fn collect_params(&self, with_select: bool) -> Vec<Value> {
+        let mut params = self.cte_params.clone();
+        // 自定义SQL不拼接查询列、FROM 和 JOIN，因此也没有对应的参数
+        if self.custom_sql.is_some() {
+            params.extend(self.custom_params.iter().cloned());
+        } else {
+            if with_select {
+                params.extend(self.select_params.iter().cloned());
+            }
+            params.extend(self.from_params.iter().cloned());
+            params.extend(self.join_params.iter().cloned());
+        }
+        params.extend(self.where_params.iter().cloned());
+        params.extend(self.having_params.iter().cloned());
+        params.extend(self.union_params.iter().cloned());
+        params
+    }
+
+    // WHERE 条件数量 (条件组算作一个)，用于中间件检查查询是否带过滤条件
+    pub fn condition_count(&self) -> usize {
+        self.where_conditions.len()
+    }
+
+    // 是否有 WHERE 条件
+    pub fn has_conditions(&self) -> bool {
+        !self.where_conditions.is_empty()
+    }
+
+    // 是否使用了自定义SQL
+    pub fn is_custom(&self) -> bool {
+        self.custom_sql.is_some()
+    }
+
+    // 检查构建过程中是否出现错误，所有执行方法在访问数据库前都会先调用
+    pub fn validate(&self) -> Result<(), Error> {
+        if let Some(error) = &self.error {
+            return Err(Error::from(error.as_str()));
+        }
+        // 方言相关的检查放在这里，与 dialect() 的调用顺序无关
+        if !self.distinct_on.is_empty() && self.dialect != Dialect::Postgres {
+            return Err(Error::from("DISTINCT ON is only supported by Postgres"));
+        }
+        if self.skip_locked && self.dialect == Dialect::Sqlite {
+            return Err(Error::from("FOR UPDATE SKIP LOCKED is not supported by SQLite"));
+        }
+        if self.skip_locked && (!self.unions.is_empty() || !self.group_by.is_empty() || self.window_count) {
+            return Err(Error::from("FOR UPDATE SKIP LOCKED cannot be combined with union, group_by or page_window"));
+        }
+        if self.rollup && self.dialect == Dialect::Sqlite {
+            return Err(Error::from("GROUP BY ROLLUP is not supported by SQLite"));
+        }
+        // SQLite 的 UNION 分支不能带括号，分支内的 ORDER BY / LIMIT 无法表达
+        if self.union_branch_paged && self.dialect == Dialect::Sqlite {
+            return Err(Error::from("SQLite does not support ORDER BY / LIMIT inside UNION branches"));
+        }
+        self.check_sortable()?;
+        if self.is_paginated() {
+            self.check_order()?;
+        }
+        check_page_bounds(self.limit.or(self.max_limit), self.offset)?;
+        if self.strict && !self.empty_value_sets.is_empty() {
+            return Err(Error::from(format!(
+                "strict mode: empty value list for column(s) {}",
+                self.empty_value_sets.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    // 执行前的检查，在 validate() 的基础上检查表名
+    fn validate_table(&self, table_name: &str) -> Result<(), Error> {
+        self.validate()?;
+        check_table_name(table_name)
+    }
+
+    // 检查排序方法使用的列是否都在 sortable_columns 中
+    fn check_sortable(&self) -> Result<(), Error> {
+        let Some(allowed) = &self.sortable_columns else {
+            return Ok(());
+        };
+        match self.order_columns.iter().find(|column| !allowed.contains(column)) {
+            Some(column) => Err(Error::from(format!("order by column `{}` is not in sortable_columns", column))),
+            None => Ok(()),
+        }
+    }
+
+    // 只保留第一个错误
+    fn set_error(&mut self, error: String) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    // 在事务中执行 f，f 返回 Ok 时提交，返回 Err 时回滚并返回该错误
+    // f 收到的事务可以直接传给各个执行方法，如 wrapper.delete(&tx, "users")
+    pub async fn transaction<R, F, Fut>(rb: &RBatis, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(RBatisTxExecutor) -> Fut,
+        Fut: Future<Output = Result<R, Error>>,
+    {
+        let tx = rb.acquire_begin().await?;
+        match f(tx.clone()).await {
+            Ok(result) => {
+                tx.commit().await?;
+                Ok(result)
+            }
+            Err(e) => {
+                // 回滚失败时连接会被丢弃，返回业务错误更便于定位问题
+                let _ = tx.rollback().await;
+                Err(e)
+            }
+        }
+    }
+
+    // 执行查询
+    pub async fn query<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.query_with_page(rb, table_name, self.effective_limit(), self.offset).await
+    }
+
+    // 依次查询 suffixes 对应的每个分表并按顺序合并结果，排序和 limit 在每个分表内分别生效
+    pub async fn query_sharded<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, suffixes: &[&str]) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let mut records = Vec::new();
+        for suffix in suffixes {
+            let wrapper = self.clone().table_suffix(suffix);
+            records.extend(wrapper.query::<T>(rb, table_name).await?);
+        }
+        Ok(records)
+    }
+
+    // 使用指定的 limit / offset 执行查询
+    async fn query_with_page<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, limit: Option<u64>, offset: Option<u64>) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        check_page_bounds(limit, offset)?;
+        let sql = self.build_sql_with_page(table_name, limit, offset);
+        let params = self.build_params();
+        rbatis::decode(self.run_query(rb, &sql, params).await?)
+    }
+
+    // 查询单条记录，没有设置 limit 时自动使用 LIMIT 1 (包括自定义SQL)，已设置的 limit 保持不变
+    pub async fn get_one<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Option<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        let sql = self.build_sql_with_page(table_name, Some(self.single_row_limit()), self.offset);
+        let params = self.build_params();
+        rbatis::decode::<Option<T>>(self.run_query(rb, &sql, params).await?)
+    }
+
+    // 取单条记录时的 LIMIT，没有设置 limit 时只取 1 行
+    fn single_row_limit(&self) -> u64 {
+        self.limit.map_or(1, |limit| self.cap_limit(limit))
+    }
+
+    // 按 single_row_limit 查询并返回第一行，用于不能按 Option 解码的类型
+    async fn first_row<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Option<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        let sql = self.build_sql_with_page(table_name, Some(self.single_row_limit()), self.offset);
+        let params = self.build_params();
+        let records: Vec<T> = rbatis::decode(self.run_query(rb, &sql, params).await?)?;
+        Ok(records.into_iter().next())
+    }
+
+    // 按唯一键查询单条记录，多于一条记录满足条件时返回错误 (错误信息包含 SQL)，用于发现重复数据
+    pub async fn get_one_strict<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Option<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        let sql = self.build_sql_with_page(table_name, Some(2), self.offset);
+        let params = self.build_params();
+        let mut records: Vec<T> = rbatis::decode(self.run_query(rb, &sql, params).await?)?;
+        if records.len() > 1 {
+            return Err(Error::from(format!("get_one_strict: more than one row matched: {}", sql)));
+        }
+        Ok(records.pop())
+    }
+
+    // 按设置的 limit 查询 (没有设置时只取 1 行)，返回结果中的第一条记录
+    pub async fn get_first_of_many<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Option<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.first_row(rb, table_name).await
+    }
+
+    // 查询单条记录，没有满足条件的记录时返回 record not found 错误 (可用 is_not_found_error 判断)，用于记录必须存在的场景
+    pub async fn get_one_required<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<T, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.get_one(rb, table_name)
+            .await?
+            .ok_or_else(|| Error::from(format!("{} in {}", NOT_FOUND_ERROR, table_name)))
+    }
+
+    // 执行查询并按 key_fn 取出的键收集为 HashMap，键重复时保留后出现的记录
+    pub async fn query_map<K, T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, key_fn: impl Fn(&T) -> K) -> Result<HashMap<K, T>, Error>
+    where
+        K: Eq + Hash,
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let records: Vec<T> = self.query(rb, table_name).await?;
+        Ok(records.into_iter().map(|record| (key_fn(&record), record)).collect())
+    }
+
+    // 执行查询并按 key_column 列的值收集为 HashMap，键重复时保留后出现的记录
+    pub async fn query_map_by<K, T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, key_column: &str) -> Result<HashMap<K, T>, Error>
+    where
+        K: Eq + Hash + for<'de> serde::Deserialize<'de>,
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let mut map = HashMap::new();
+        for row in self.query_rows(rb, table_name).await? {
+            let key = row_key(&row, key_column)?;
+            map.insert(key, rbs::from_value::<T>(row)?);
+        }
+        Ok(map)
+    }
+
+    // 执行查询并按 key_column 列的值分组，用于在应用中关联一对多的数据，组内记录保持查询顺序
+    pub async fn query_group_map<K, T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, key_column: &str) -> Result<HashMap<K, Vec<T>>, Error>
+    where
+        K: Eq + Hash + for<'de> serde::Deserialize<'de>,
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let mut map: HashMap<K, Vec<T>> = HashMap::new();
+        for row in self.query_rows(rb, table_name).await? {
+            let key = row_key(&row, key_column)?;
+            map.entry(key).or_default().push(rbs::from_value::<T>(row)?);
+        }
+        Ok(map)
+    }
+
+    // 执行查询并把每行按列顺序解码为元组，如 query_tuple::<(u64, String)>
+    // 元组元素与 select 指定的列一一对应，列的顺序即 SQL 结果集中的顺序
+    pub async fn query_tuple<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Vec<T>, Error>
+    where
+        T: for<'de> serde::Deserialize<'de>,
+    {
+        self.query_rows(rb, table_name)
+            .await?
+            .into_iter()
+            .map(decode_tuple::<T>)
+            .collect()
+    }
+
+    // 执行查询，返回未解码的原始行
+    async fn query_rows(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Vec<Value>, Error> {
+        self.validate_table(table_name)?;
+        let sql = self.build_sql(table_name);
+        let params = self.build_params();
+        match self.run_query(rb, &sql, params).await? {
+            Value::Array(rows) => Ok(rows),
+            Value::Null => Ok(vec![]),
+            value => Ok(vec![value]),
+        }
+    }
+
+    // 执行查询，结果解码为 JSON 对象，适用于没有对应结构体的动态查询
+    pub async fn query_json(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Vec<serde_json::Value>, Error> {
+        self.query::<serde_json::Value>(rb, table_name).await
+    }
+
+    // 分页查询，记录解码为 JSON 对象，列名为 select 中的列名或别名
+    pub async fn page_json(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Page<serde_json::Value>, Error> {
+        self.page::<serde_json::Value>(rb, table_name, page_no, page_size).await
+    }
+
+    // 查询单条记录并解码为 JSON 对象
+    pub async fn get_one_json(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<Option<serde_json::Value>, Error> {
+        // serde_json::Value 可以接收任意结构，不能用 get_one 的 Option 解码，这里直接取第一行
+        self.first_row(rb, table_name).await
+    }
+
+    // 判断是否存在满足条件的记录，只查询 SELECT EXISTS(...)，不解码整行数据
+    pub async fn exists_row(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<bool, Error> {
+        self.validate_table(table_name)?;
+        let sql = self.build_exists_sql(table_name);
+        let params = self.build_exists_params();
+        let value = first_column(self.run_query(rb, &sql, params).await?);
+        // MySQL/SQLite 返回 0/1，Postgres 返回布尔值
+        Ok(match value {
+            Value::Bool(exists) => exists,
+            value => value.as_i64().unwrap_or(0) != 0,
+        })
+    }
+
+    // 构建存在性查询语句，自定义SQL (可能已有 LIMIT) 和 UNION (各分支列数需一致) 整体包装为派生表
+    pub fn build_exists_sql(&self, table_name: &str) -> String {
+        let mut sql = self.build_with();
+        if self.wraps_exists() {
+            let inner_sql = self.build_compound(table_name, &self.select_list(), true);
+            sql.push_str(&format!("SELECT EXISTS(SELECT 1 FROM ({}) t)", inner_sql));
+        } else {
+            sql.push_str(&format!("SELECT EXISTS({} LIMIT 1)", self.build_compound(table_name, "1", true)));
+        }
+        sql
+    }
+
+    // 存在性查询的绑定参数，与 build_exists_sql 对应
+    pub fn build_exists_params(&self) -> Vec<Value> {
+        self.collect_params(self.wraps_exists())
+    }
+
+    fn wraps_exists(&self) -> bool {
+        self.custom_sql.is_some() || !self.unions.is_empty()
+    }
+
+    // 执行删除，返回影响行数等执行结果
+    pub async fn delete(self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<ExecResult, Error> {
+        self.validate_table(table_name)?;
+        self.check_unfiltered("delete")?;
+        if self.uses_multi_table_delete() && (!self.order_by.is_empty() || self.limit.is_some()) {
+            return Err(Error::from("MySQL DELETE with joins does not support ORDER BY / LIMIT"));
+        }
+        let delete_sql = self.build_delete_head(table_name);
+        // 多表形式的 JOIN 在语句头部中，参数排在 WHERE 参数之前
+        let head_params = if self.uses_multi_table_delete() { self.join_params.clone() } else { vec![] };
+        let mut wrapper = self.custom_sql_with(&delete_sql, head_params);
+        wrapper.max_limit = None;
+        wrapper.skip_locked = false;
+        let sql = wrapper.build_sql(table_name);
+        let params = wrapper.build_params();
+        wrapper.run_exec(rb, &sql, params).await
+    }
+
+    // 没有 WHERE 条件的删除、更新会作用于整张表，需要通过 allow_delete_all 显式允许
+    // 删除、更新会用语句头部替换自定义SQL，自定义SQL中的条件不会生效，直接拒绝
+    fn check_unfiltered(&self, op: &str) -> Result<(), Error> {
+        if self.is_custom() {
+            return Err(Error::from(format!(
+                "{} does not use custom_sql, its conditions would be discarded; use where_raw instead",
+                op
+            )));
+        }
+        // 这些子句只对查询有效，拼接到删除、更新语句中会生成无效或含义不同的 SQL
+        let select_only = [
+            ("union", !self.unions.is_empty()),
+            ("group_by", !self.group_by.is_empty()),
+            ("having", !self.having.is_empty()),
+            ("distinct_on", !self.distinct_on.is_empty()),
+            ("from_subquery", self.from_source.is_some()),
+        ];
+        if let Some((clause, _)) = select_only.iter().find(|(_, used)| *used) {
+            return Err(Error::from(format!("{} cannot be used with {}, it only applies to queries", clause, op)));
+        }
+        if !self.has_conditions() && !self.allow_delete_all {
+            return Err(Error::from(format!(
+                "{} without conditions is refused, call allow_delete_all() to affect every row",
+                op
+            )));
+        }
+        Ok(())
+    }
+
+    // 清空整张表，MySQL/Postgres 使用 TRUNCATE TABLE，SQLite 没有 TRUNCATE，使用 DELETE FROM
+    // 只用于测试数据重置等场景，wrapper 中有条件时返回错误，避免误以为会按条件删除
+    pub async fn truncate(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<ExecResult, Error> {
+        self.validate_table(table_name)?;
+        if self.has_conditions() || self.is_custom() {
+            return Err(Error::from("truncate does not accept conditions, use delete instead"));
+        }
+        let table = self.table_ident(&self.sharded_table(table_name));
+        let sql = match self.dialect {
+            Dialect::MySql | Dialect::Postgres => format!("TRUNCATE TABLE {}", table),
+            Dialect::Sqlite => format!("DELETE FROM {}", table),
+        };
+        self.run_exec(rb, &sql, vec![]).await
+    }
+
+    // 分批删除，每次最多删除 chunk_size 条，直到没有满足条件的记录，返回删除的总行数
+    // 避免一次删除大量数据长时间锁表。MySQL 使用 DELETE ... LIMIT，
+    // Postgres/SQLite 不支持 DELETE ... LIMIT，按 ctid / rowid 子查询分批
+    pub async fn delete_in_chunks(self, rb: &(impl Executor + ?Sized), table_name: &str, chunk_size: u64) -> Result<u64, Error> {
+        self.validate_table(table_name)?;
+        self.check_unfiltered("delete")?;
+        if chunk_size == 0 {
+            return Err(Error::from("chunk_size must be greater than 0"));
+        }
+        // MySQL 带 JOIN 的删除只能用多表形式，不支持 LIMIT
+        if self.dialect == Dialect::MySql && !self.join_conditions.is_empty() {
+            return Err(Error::from("delete_in_chunks with joins is not supported on MySQL"));
+        }
+        let (sql, params) = self.build_chunk_delete(table_name, chunk_size);
+        let mut total = 0;
+        loop {
+            let result = self.run_exec(rb, &sql, params.clone()).await?;
+            total += result.rows_affected;
+            // 删除的行数不足一批时已经没有剩余数据
+            if result.rows_affected < chunk_size {
+                return Ok(total);
+            }
+        }
+    }
+
+    // 构建一批删除的语句和参数
+    fn build_chunk_delete(&self, table_name: &str, chunk_size: u64) -> (String, Vec<Value>) {
+        let table = self.table_ident(&self.sharded_table(table_name));
+        if self.dialect == Dialect::MySql {
+            // 多表形式的 DELETE alias FROM ... 不支持 LIMIT，这里使用单表形式
+            let head = match &self.alias {
+                Some(alias) => format!("DELETE FROM {} AS {}", table, alias),
+                None => format!("DELETE FROM {}", table),
+            };
+            let mut wrapper = self.clone().custom_sql(&head);
+            wrapper.max_limit = None;
+            wrapper.skip_locked = false;
+            let sql = wrapper.build_sql_with_page(table_name, Some(chunk_size), None);
+            return (sql, wrapper.build_params());
+        }
+
+        // 行标识加上表名或别名限定，有 JOIN 时 ctid / rowid 不会有歧义
+        let row_id = if self.dialect == Dialect::Postgres { "ctid" } else { "rowid" };
+        let outer_ref = table.rsplit_once(char::is_whitespace).map_or(table.as_str(), |(_, alias)| alias);
+        let inner_ref = self.alias.as_deref().unwrap_or(outer_ref);
+        let mut inner = self.clone();
+        inner.select_columns = vec![format!("{}.{}", inner_ref, row_id)];
+        inner.select_params.clear();
+        inner.max_limit = None;
+        inner.skip_locked = false;
+        let sql = format!(
+            "DELETE FROM {} WHERE {}.{} IN ({})",
+            table,
+            outer_ref,
+            row_id,
+            inner.build_sql_with_page(table_name, Some(chunk_size), None)
+        );
+        (sql, inner.build_params())
+    }
+
+    // 执行调用方提供的语句头部，wrapper 追加 WHERE / ORDER BY / LIMIT，如 UPDATE member SET flagged = 1
+    // 语句头部中的 ? 按顺序绑定 params，之后是条件参数；与 delete 一样拒绝没有条件的执行
+    // table_name 只用于检查，语句中的表名以 sql_prefix 为准
+    pub async fn exec(&self, rb: &(impl Executor + ?Sized), sql_prefix: &str, params: Vec<Value>, table_name: &str) -> Result<ExecResult, Error> {
+        self.validate_table(table_name)?;
+        self.check_unfiltered("exec")?;
+        let mut wrapper = self.clone().custom_sql_with(sql_prefix, params);
+        wrapper.max_limit = None;
+        wrapper.skip_locked = false;
+        let sql = wrapper.build_sql(table_name);
+        let params = wrapper.build_params();
+        wrapper.run_exec(rb, &sql, params).await
+    }
+
+    // 按条件更新 set / set_entity 设置的列，与 delete 一样拒绝没有条件的更新
+    pub async fn update(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<ExecResult, Error> {
+        self.validate_table(table_name)?;
+        self.check_unfiltered("update")?;
+        if self.set_values.is_empty() {
+            return Err(Error::from("update has no columns to set, call set or set_entity"));
+        }
+        if !self.join_conditions.is_empty() {
+            return Err(Error::from("update with joins is not supported, use exec instead"));
+        }
+        let sql = self.build_update_sql(table_name);
+        let params = self.build_update_params();
+        self.run_exec(rb, &sql, params).await
+    }
+
+    // 构建 UPDATE 语句，与 build_update_params 对应
+    pub fn build_update_sql(&self, table_name: &str) -> String {
+        let mut head = format!("UPDATE {}", self.table_ident(&self.sharded_table(table_name)));
+        if let Some(alias) = &self.alias {
+            head.push_str(&format!(" AS {}", alias));
+        }
+        let sets: Vec<String> = self.set_values.iter().map(|(column, _)| format!("{} = ?", column)).collect();
+        head.push_str(" SET ");
+        head.push_str(&sets.join(", "));
+        self.update_statement(&head).build_sql(table_name)
+    }
+
+    // UPDATE 语句的绑定参数，SET 的值在条件参数之前
+    pub fn build_update_params(&self) -> Vec<Value> {
+        self.update_statement("").build_params()
+    }
+
+    // 用 UPDATE 语句头部替换查询部分，只保留条件、排序和 LIMIT
+    fn update_statement(&self, head: &str) -> QueryWrapper {
+        let params = self.set_values.iter().map(|(_, value)| value.clone()).collect();
+        let mut wrapper = self.clone().custom_sql_with(head, params);
+        wrapper.max_limit = None;
+        wrapper.skip_locked = false;
+        wrapper
+    }
+
+    // 插入实体，冲突时按 on_conflict / do_update / do_nothing 更新或保留已有记录，值为 None 的字段不插入
+    pub async fn upsert<T: Serialize>(&self, rb: &(impl Executor + ?Sized), table_name: &str, entity: &T) -> Result<ExecResult, Error> {
+        self.validate_table(table_name)?;
+        if self.has_conditions() || self.is_custom() {
+            return Err(Error::from("upsert does not accept conditions"));
+        }
+        let (columns, params): (Vec<String>, Vec<Value>) = entity_columns("upsert", entity)?.into_iter().unzip();
+        if columns.is_empty() {
+            return Err(Error::from("upsert has no columns to insert"));
+        }
+        let updates_on_conflict = self.conflict_update.as_ref().is_none_or(|update| !update.is_empty());
+        if self.dialect != Dialect::MySql && updates_on_conflict && self.conflict_columns.is_empty() {
+            return Err(Error::from("upsert with DO UPDATE requires on_conflict columns on Postgres / SQLite"));
+        }
+        let columns: Vec<&str> = columns.iter().map(String::as_str).collect();
+        let sql = self.build_upsert_sql(table_name, &columns);
+        self.run_exec(rb, &sql, params).await
+    }
+
+    // 构建插入一行的 upsert 语句，columns 按顺序对应绑定参数
+    // Postgres/SQLite: ON CONFLICT (...) DO UPDATE SET c = EXCLUDED.c / DO NOTHING
+    // MySQL: ON DUPLICATE KEY UPDATE c = VALUES(c)，DO NOTHING 时把第一列更新为自身
+    pub fn build_upsert_sql(&self, table_name: &str, columns: &[&str]) -> String {
+        let placeholders = vec!["?"; columns.len()].join(", ");
+        let mut sql = format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.table_ident(&self.sharded_table(table_name)),
+            columns.join(", "),
+            placeholders
+        );
+        let updates: Vec<&str> = match &self.conflict_update {
+            Some(update) => update.iter().map(String::as_str).collect(),
+            None => columns.iter().copied().filter(|c| !self.conflict_columns.iter().any(|k| k == c)).collect(),
+        };
+        match self.dialect {
+            Dialect::MySql => {
+                let sets: Vec<String> = match updates.is_empty() {
+                    true => columns.iter().take(1).map(|c| format!("{} = {}", c, c)).collect(),
+                    false => updates.iter().map(|c| format!("{} = VALUES({})", c, c)).collect(),
+                };
+                sql.push_str(&format!(" ON DUPLICATE KEY UPDATE {}", sets.join(", ")));
+            }
+            Dialect::Postgres | Dialect::Sqlite => {
+                sql.push_str(" ON CONFLICT");
+                if !self.conflict_columns.is_empty() {
+                    sql.push_str(&format!(" ({})", self.conflict_columns.join(", ")));
+                }
+                if updates.is_empty() {
+                    sql.push_str(" DO NOTHING");
+                } else {
+                    let sets: Vec<String> = updates.iter().map(|c| format!("{} = EXCLUDED.{}", c, c)).collect();
+                    sql.push_str(&format!(" DO UPDATE SET {}", sets.join(", ")));
+                }
+            }
+        }
+        sql
+    }
+
+    // 把查询结果直接写入另一张表 INSERT INTO target (columns) SELECT ...，数据不经过应用程序，适用于归档和迁移
+    // columns 为空时不指定目标列，按 select 的列顺序写入
+    pub async fn insert_into(&self, rb: &(impl Executor + ?Sized), table_name: &str, target_table: &str, columns: &[&str]) -> Result<ExecResult, Error> {
+        self.validate_table(table_name)?;
+        check_table_name(target_table)?;
+        let sql = self.build_insert_select_sql(table_name, target_table, columns);
+        let params = self.build_params();
+        self.run_exec(rb, &sql, params).await
+    }
+
+    // 构建 INSERT INTO ... SELECT 语句，参数与 build_params() 相同
+    pub fn build_insert_select_sql(&self, table_name: &str, target_table: &str, columns: &[&str]) -> String {
+        let mut sql = format!("INSERT INTO {}", self.table_ident(target_table));
+        if !columns.is_empty() {
+            sql.push_str(&format!(" ({})", columns.join(", ")));
+        }
+        sql.push(' ');
+        sql.push_str(&self.build_sql(table_name));
+        sql
+    }
+
+    // 统计满足条件的记录数
+    pub async fn count(&self, rb: &(impl Executor + ?Sized), table_name: &str) -> Result<u64, Error> {
+        self.validate_table(table_name)?;
+        let count_sql = self.build_count_sql(table_name);
+        let params = self.build_count_params();
+        let rows = self.run_query(rb, &count_sql, params).await?;
+        if self.count_sql_override.is_none() {
+            return decode_count(first_column(rows));
+        }
+        single_column(rows)
+            .and_then(decode_count)
+            .map_err(|e| Error::from(format!("count_sql override must return a single numeric column: {}", e)))
+    }
+
+    // 修改分页方法
+    pub async fn page<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        if page_size == 0 {
+            return Err(Error::from("page_size must be greater than 0"));
+        }
+        self.check_order()?;
+        // 页码从 1 开始，0 按第一页处理
+        let page_no = page_no.max(1);
+        let page_size = self.cap_limit(page_size);
+
+        if self.skip_count {
+            return self.page_without_count(rb, table_name, page_no, page_size).await;
+        }
+
+        if self.window_count && self.supports_window_count() {
+            // 本页没有数据时无法得到总数，继续按统计查询处理
+            if let Some(page) = self.page_with_window_count(rb, table_name, page_no, page_size).await? {
+                return Ok(page);
+            }
+        }
+
+        if self.concurrent_page {
+            return self.page_concurrent(rb, table_name, page_no, page_size).await;
+        }
+
+        // 1. 先查询总记录数
+        let total = self.count(rb, table_name).await?;
+
+        // 2. 如果有数据，再查询分页数据
+        self.page_from_total(rb, table_name, total, page_no, page_size).await
+    }
+
+    // 分页查询，总数优先从 cache 中读取，缓存没有时查询并按 ttl 写入缓存
+    // 适用于统计很慢且总数变化不频繁的大表，数据变更后的缓存失效由调用方处理
+    pub async fn page_cached<T>(
+        &self,
+        rb: &(impl Executor + ?Sized),
+        table_name: &str,
+        page_no: u64,
+        page_size: u64,
+        cache: &dyn CountCache,
+        ttl: Duration,
+    ) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        if page_size == 0 {
+            return Err(Error::from("page_size must be greater than 0"));
+        }
+        self.check_order()?;
+        let page_no = page_no.max(1);
+        let page_size = self.cap_limit(page_size);
+
+        let key = self.count_cache_key(table_name);
+        let total = match cache.get(&key).await {
+            Some(total) => total,
+            None => {
+                let total = self.count(rb, table_name).await?;
+                cache.put(&key, total, ttl).await;
+                total
+            }
+        };
+        self.page_from_total(rb, table_name, total, page_no, page_size).await
+    }
+
+    // 使用估算总数的分页查询，适用于行数很大、只需要显示大概总数的表
+    // 没有任何过滤条件时从 information_schema.tables (MySQL) 或 pg_class.reltuples (Postgres) 读取估算行数，
+    // 有条件、SQLite 或估算值为 0 时使用 COUNT(*)。返回的 total_is_estimate 表示总数是否为估算值
+    pub async fn page_estimated<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        self.validate_table(table_name)?;
+        if page_size == 0 {
+            return Err(Error::from("page_size must be greater than 0"));
+        }
+        self.check_order()?;
+        let page_no = page_no.max(1);
+        let page_size = self.cap_limit(page_size);
+
+        let estimate = match self.build_estimate_sql(table_name) {
+            Some((sql, params)) => decode_count(first_column(self.run_query(rb, &sql, params).await?)).unwrap_or(0),
+            None => 0,
+        };
+        if estimate == 0 {
+            return self.page(rb, table_name, page_no, page_size).await;
+        }
+        let mut page: Page<T> = self.page_from_total(rb, table_name, estimate, page_no, page_size).await?;
+        page.total_is_estimate = true;
+        Ok(page)
+    }
+
+    // 查询估算行数的语句，只有统计整张表时才能使用估算值
+    fn build_estimate_sql(&self, table_name: &str) -> Option<(String, Vec<Value>)> {
+        let filtered = !self.where_conditions.is_empty()
+            || !self.join_conditions.is_empty()
+            || !self.ctes.is_empty()
+            || self.from_source.is_some()
+            || self.count_sql_override.is_some()
+            || self.wraps_count();
+        if filtered {
+            return None;
+        }
+        // 按分表后缀估算实际执行查询的分表
+        let table_name = self.sharded_table(table_name);
+        let table = table_name.split_whitespace().next()?;
+        match self.dialect {
+            Dialect::MySql => Some(match table.split_once('.') {
+                Some((schema, table)) => (
+                    "SELECT TABLE_ROWS FROM information_schema.tables WHERE table_schema = ? AND table_name = ?".to_string(),
+                    vec![Value::from(schema), Value::from(table)],
+                ),
+                None => (
+                    "SELECT TABLE_ROWS FROM information_schema.tables WHERE table_schema = DATABASE() AND table_name = ?".to_string(),
+                    vec![Value::from(table)],
+                ),
+            }),
+            // 从未 ANALYZE 的表 reltuples 为 -1，按没有估算值处理
+            Dialect::Postgres => Some((
+                "SELECT reltuples::bigint FROM pg_class WHERE oid = to_regclass(?)".to_string(),
+                vec![Value::from(table)],
+            )),
+            Dialect::Sqlite => None,
+        }
+    }
+
+    // 总数缓存的 key，统计SQL和绑定参数都相同时共用缓存
+    pub fn count_cache_key(&self, table_name: &str) -> String {
+        format!("{}|{:?}", self.build_count_sql(table_name), self.build_count_params())
+    }
+
+    // 已知总数时查询指定页，处理页码超出总页数的情况
+    async fn page_from_total<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, total: u64, page_no: u64, page_size: u64) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        if total == 0 {
+            // 没有数据时返回空页
+            return Ok(Page::empty(page_no, page_size));
+        }
+        let pages = total.div_ceil(page_size);
+        let page_no = match self.page_overflow {
+            PageOverflow::Last if page_no > pages => pages,
+            _ => page_no,
+        };
+
+        // 请求的页码超出总页数时不再查询，直接返回空数据，has_next 为 false
+        if page_no > pages {
+            return Ok(Page::new(vec![], total, page_no, page_size));
+        }
+
+        let records = self.page_records(rb, table_name, page_no, page_size).await?;
+        Ok(Page::new(records, total, page_no, page_size))
+    }
+
+    // 是否可以用 COUNT(*) OVER() 统计总数，自定义SQL、UNION、DISTINCT ON 和深分页优化不支持
+    fn supports_window_count(&self) -> bool {
+        self.custom_sql.is_none() && self.unions.is_empty() && self.distinct_on.is_empty() && self.deep_offset_pk().is_none()
+    }
+
+    // 在查询列中追加 COUNT(*) OVER()，一次查询同时得到本页数据和总数，本页没有数据时返回 None
+    async fn page_with_window_count<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Option<Page<T>>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let offset = (page_no - 1).saturating_mul(page_size);
+        let mut wrapper = self.clone().limit(page_size).offset(offset);
+        if wrapper.select_columns.is_empty() {
+            wrapper.select_columns.push("*".to_string());
+        }
+        wrapper.select_columns.push(format!("COUNT(*) OVER() AS {}", WINDOW_TOTAL_COLUMN));
+
+        let mut rows = wrapper.query_rows(rb, table_name).await?;
+        if rows.is_empty() {
+            return Ok(None);
+        }
+        let key = Value::String(WINDOW_TOTAL_COLUMN.to_string());
+        let mut total = 0;
+        for row in rows.iter_mut() {
+            if let Value::Map(row) = row {
+                total = decode_count(row.remove(&key))?;
+            }
+        }
+        let records = rbs::from_value::<Vec<T>>(Value::Array(rows))?;
+        Ok(Some(Page::new(records, total, page_no, page_size)))
+    }
+
+    // 同时执行统计和数据查询，页码超出总页数时丢弃查询结果，PageOverflow::Last 会再查询一次最后一页
+    async fn page_concurrent<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let (total, records) = futures::future::try_join(
+            self.count(rb, table_name),
+            self.page_records::<T>(rb, table_name, page_no, page_size),
+        )
+        .await?;
+
+        let pages = total.div_ceil(page_size);
+        if total == 0 || page_no <= pages {
+            return Ok(Page::new(records, total, page_no, page_size));
+        }
+        match self.page_overflow {
+            PageOverflow::Last => {
+                let records = self.page_records(rb, table_name, pages, page_size).await?;
+                Ok(Page::new(records, total, pages, page_size))
+            }
+            PageOverflow::Empty => Ok(Page::new(vec![], total, page_no, page_size)),
+        }
+    }
+
+    // 查询指定页的数据
+    async fn page_records<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let offset = (page_no - 1).saturating_mul(page_size);
+        match self.deep_offset_pk() {
+            Some(pk) => self.clone().limit(page_size).offset(offset).query_by_ids(rb, table_name, pk).await,
+            // 分页参数在构建语句时传入，不复制 wrapper
+            None => self.query_with_page(rb, table_name, Some(page_size), Some(offset)).await,
+        }
+    }
+
+    // 可以使用深分页优化时返回主键列
+    fn deep_offset_pk(&self) -> Option<&str> {
+        if self.custom_sql.is_some() || !self.unions.is_empty() || !self.group_by.is_empty() || !self.distinct_on.is_empty() {
+            return None;
+        }
+        self.deep_offset_pk.as_deref()
+    }
+
+    // 先查询当前页的主键，再按主键查询整行，排序与原查询一致
+    async fn query_by_ids<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, pk: &str) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let mut id_query = self.clone();
+        id_query.select_columns = vec![pk.to_string()];
+        id_query.select_params.clear();
+        let ids: Vec<Value> = id_query
+            .query_rows(rb, table_name)
+            .await?
+            .into_iter()
+            .map(first_column)
+            .collect();
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut row_query = self.clone();
+        row_query.where_conditions.clear();
+        row_query.where_params.clear();
+        row_query.limit = None;
+        row_query.offset = None;
+        if row_query.order_by.is_empty() {
+            row_query.order_by.extend(self.default_order.clone());
+        }
+        row_query.in_(pk, ids).query(rb, table_name).await
+    }
+
+    // 按分页请求查询，先用 normalize() 修正页码和每页大小，避免超大的 page_size 直接查询数据库
+    pub async fn page_with<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, req: &PageRequest) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let req = req.normalize();
+        self.page(rb, table_name, req.page_no, req.page_size).await
+    }
+
+    // 不查询总数的分页，多取一条记录判断是否有下一页
+    async fn page_without_count<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_no: u64, page_size: u64) -> Result<Page<T>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let offset = (page_no - 1).saturating_mul(page_size);
+        let mut records: Vec<T> = self
+            .query_with_page(rb, table_name, Some(page_size.saturating_add(1)), Some(offset))
+            .await?;
+
+        let has_next = records.len() as u64 > page_size;
+        records.truncate(page_size as usize);
+
+        let mut page = Page::new(records, 0, page_no, page_size);
+        page.has_next = has_next;
+        Ok(page)
+    }
+
+    // 游标分页 (keyset)，按 key_column 排序并从 cursor 之后开始取 page_size 条，深分页时比 OFFSET 高效
+    // key_column 必须在查询结果中且值唯一，多取一条判断 has_next；会替换已有的排序和 limit / offset
+    pub async fn page_after<T, C>(
+        &self,
+        rb: &(impl Executor + ?Sized),
+        table_name: &str,
+        cursor: Option<C>,
+        page_size: u64,
+        key_column: &str,
+        asc: bool,
+    ) -> Result<CursorPage<T, C>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+        C: Into<Value> + for<'de> serde::Deserialize<'de>,
+    {
+        if page_size == 0 {
+            return Err(Error::from("page_size must be greater than 0"));
+        }
+        let page_size = self.cap_limit(page_size);
+        let mut wrapper = self.clone();
+        wrapper.apply_keyset(cursor.map(Into::into), page_size, key_column, asc);
+        let (records, last_key, has_next) = wrapper.fetch_keyset(rb, table_name, page_size, key_column).await?;
+        let next_cursor = last_key.map(rbs::from_value::<C>).transpose()?;
+        Ok(CursorPage { records, next_cursor, has_next })
+    }
+
+    // 设置游标分页的条件、排序和 limit，多取一条判断是否有下一页
+    // page_size 已按 max_limit 截断，多取的一条不再受 max_limit 限制
+    fn apply_keyset(&mut self, cursor: Option<Value>, page_size: u64, key_column: &str, asc: bool) {
+        self.max_limit = None;
+        if let Some(cursor) = cursor {
+            let op = if asc { ">" } else { "<" };
+            self.where_conditions.push(format!("{} {} ?", key_column, op));
+            self.where_params.push(cursor);
+        }
+        self.order_by = vec![format!("{} {}", key_column, if asc { "ASC" } else { "DESC" })];
+        self.order_columns.clear();
+        self.offset = None;
+        self.limit = Some(page_size.saturating_add(1));
+    }
+
+    // 执行游标分页查询，返回本页数据、最后一行的键值和是否有下一页
+    async fn fetch_keyset<T>(&self, rb: &(impl Executor + ?Sized), table_name: &str, page_size: u64, key_column: &str) -> Result<(Vec<T>, Option<Value>, bool), Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        let mut rows = self.query_rows(rb, table_name).await?;
+        let has_next = rows.len() as u64 > page_size;
+        rows.truncate(page_size as usize);
+
+        let last_key = match rows.last() {
+            Some(row) => Some(row_key::<Value>(row, key_column)?),
+            None => None,
+        };
+        let records = rbs::from_value::<Vec<T>>(Value::Array(rows))?;
+        Ok((records, last_key, has_next))
+    }
+
+    // 按页遍历所有满足条件的记录，每次 next_page() 返回一页数据，适用于批处理
+    // 使用 limit / offset 翻页，遍历过程中有数据插入时可能出现重复或遗漏，这种场景使用 iter_pages_by_key
+    pub fn iter_pages<'a, E: Executor + ?Sized>(&self, rb: &'a E, table_name: &str, page_size: u64) -> PageIter<'a, E> {
+        PageIter::new(self.clone(), rb, table_name, self.cap_limit(page_size), None)
+    }
+
+    // 按 key_column 升序分批读取所有满足条件的记录，每批最多 chunk_size 条交给 f 处理，适用于夜间重建索引等批处理任务
+    // key_column 必须有序且唯一，使用游标翻页，处理过程中有数据插入也不会重复或遗漏；f 返回错误时立即停止并返回该错误
+    pub async fn for_each_chunk<T, F, Fut>(&self, rb: &(impl Executor + ?Sized), table_name: &str, chunk_size: u64, key_column: &str, mut f: F) -> Result<(), Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+        F: FnMut(Vec<T>) -> Fut,
+        Fut: Future<Output = Result<(), Error>>,
+    {
+        let mut pages = self.iter_pages_by_key(rb, table_name, chunk_size, key_column, true);
+        while let Some(records) = pages.next_page::<T>().await? {
+            f(records).await?;
+        }
+        Ok(())
+    }
+
+    // 逐条返回满足条件的记录，内部按 batch_size 分批查询，内存中最多保留一批数据，适用于大量数据导出
+    // 每批是一次独立的查询，批次之间不占用连接；需要按游标分批时使用 iter_pages_by_key(..).into_stream()
+    pub fn stream<'a, T, E>(&self, rb: &'a E, table_name: &str, batch_size: u64) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de> + 'a,
+        E: Executor + ?Sized,
+    {
+        self.iter_pages(rb, table_name, batch_size).into_stream()
+    }
+
+    // 按 key_column 游标遍历所有满足条件的记录，翻页不受数据插入影响
+    pub fn iter_pages_by_key<'a, E: Executor + ?Sized>(&self, rb: &'a E, table_name: &str, page_size: u64, key_column: &str, asc: bool) -> PageIter<'a, E> {
+        PageIter::new(self.clone(), rb, table_name, self.cap_limit(page_size), Some((key_column.to_string(), asc)))
+    }
+
+    // 执行查询语句，开启重试时遇到临时性错误按退避时间重试
+    async fn run_query(&self, rb: &(impl Executor + ?Sized), sql: &str, params: Vec<Value>) -> Result<Value, Error> {
+        self.log_sql(sql, &params);
+        self.run_with_retry(true, || self.with_timeout(sql, rb.query(sql, params.clone()))).await
+    }
+
+    // 执行更新语句，开启重试时遇到临时性错误按退避时间重试
+    async fn run_exec(&self, rb: &(impl Executor + ?Sized), sql: &str, params: Vec<Value>) -> Result<ExecResult, Error> {
+        self.log_sql(sql, &params);
+        self.run_with_retry(self.idempotent, || self.with_timeout(sql, rb.exec(sql, params.clone()))).await
+    }
+
+    // 按重试策略执行，retryable 为 false 或没有设置策略时只执行一次，每次重试的等待时间翻倍
+    async fn run_with_retry<R, F, Fut>(&self, retryable: bool, mut f: F) -> Result<R, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, Error>>,
+    {
+        let policy = match &self.retry_policy {
+            Some(policy) if retryable => policy,
+            _ => return f().await,
+        };
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Err(e) if attempt < policy.max_attempts && policy.should_retry(&e) => {
+                    let backoff = policy.backoff.saturating_mul(2u32.saturating_pow(attempt - 1));
+                    #[cfg(feature = "log")]
+                    log::warn!("rbatis-wrapper retry {}/{} after {:?}: {}", attempt, policy.max_attempts - 1, backoff, e);
+                    rbatis::rbdc::rt::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // 按 timeout 的设置等待语句执行完成
+    async fn with_timeout<R>(&self, sql: &str, fut: impl Future<Output = Result<R, Error>>) -> Result<R, Error> {
+        match self.timeout {
+            Some(timeout) => match rbatis::rbdc::rt::timeout(timeout, fut).await {
+                Ok(result) => result,
+                Err(_) => Err(Error::from(format!("{} after {}ms: {}", TIMEOUT_ERROR, timeout.as_millis(), sql))),
+            },
+            None => fut.await,
+        }
+    }
+
+    // 开启 log feature 时，执行前以 debug 级别输出 SQL 和参数
+    fn log_sql(&self, sql: &str, params: &[Value]) {
+        #[cfg(feature = "log")]
+        {
+            if self.redact_params {
+                log::debug!("rbatis-wrapper sql: {} params: [{} redacted]", sql, params.len());
+            } else {
+                log::debug!("rbatis-wrapper sql: {} params: {:?}", sql, params);
+            }
+        }
+        #[cfg(not(feature = "log"))]
+        let _ = (self.redact_params, sql, params);
+    }
+
+    // 生成转义后的字符串字面量，只用于无法绑定参数的位置
+    fn quote(&self, value: &str) -> String {
+        let mut escaped = value.replace('\'', "''");
+        // MySQL 默认把反斜杠当作转义字符
+        if self.dialect == Dialect::MySql {
+            escaped = escaped.replace('\\', "\\\\");
+        }
+        format!("'{}'", escaped)
+    }
+
+    // 查询来源，优先使用 from_subquery 设置的派生表
+    fn table_source(&self, table_name: &str) -> String {
+        let table_name = self.table_ident(&self.sharded_table(table_name));
+        match (&self.from_source, &self.alias) {
+            (Some(source), _) => source.clone(),
+            (None, Some(alias)) => format!("{} AS {}", table_name, alias),
+            (None, None) => table_name,
+        }
+    }
+
+    // 主表名拼接 table_suffix，表名后的别名保持不变
+    fn sharded_table(&self, table_name: &str) -> String {
+        match &self.table_suffix {
+            Some(suffix) if !table_name.is_empty() => match table_name.split_once(char::is_whitespace) {
+                Some((name, rest)) => format!("{}{} {}", name, suffix, rest),
+                None => format!("{}{}", table_name, suffix),
+            },
+            _ => table_name.to_string(),
+        }
+    }
+
+    // 开启 quote_table_name 时给表名加上方言对应的引号，schema.table 分别加引号，表名后的别名保持不变
+    fn table_ident(&self, table_name: &str) -> String {
+        if !self.quote_table || table_name.is_empty() {
+            return table_name.to_string();
+        }
+        let (name, rest) = match table_name.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (table_name, None),
+        };
+        let (open, close) = match self.dialect {
+            Dialect::MySql => ('`', '`'),
+            Dialect::Postgres | Dialect::Sqlite => ('"', '"'),
+        };
+        let quoted: Vec<String> = name.split('.').map(|part| format!("{}{}{}", open, part, close)).collect();
+        match rest {
+            Some(rest) => format!("{} {}", quoted.join("."), rest),
+            None => quoted.join("."),
+        }
+    }
+
+    // 拼接 JOIN 条件，{table} 替换为实际的主表名
+    fn push_joins(&self, sql: &mut String, table_name: &str) {
+        if self.join_conditions.is_empty() {
+            return;
+        }
+        sql.push(' ');
+        let joins = self.join_conditions.join(" ");
+        if joins.contains("{table}") {
+            let table = self.sharded_table(table_name);
+            let name = table.split_whitespace().next().unwrap_or_default();
+            sql.push_str(&joins.replace("{table}", &self.table_ident(name)));
+        } else {
+            sql.push_str(&joins);
+        }
+    }
+
+    // MySQL 带 JOIN 的别名删除只能使用多表形式 DELETE alias FROM table AS alias，该形式不支持 ORDER BY / LIMIT
+    fn uses_multi_table_delete(&self) -> bool {
+        self.dialect == Dialect::MySql && self.alias.is_some() && !self.join_conditions.is_empty()
+    }
+
+    // 删除语句头部，没有 JOIN 时使用单表形式，可以追加 ORDER BY / LIMIT
+    fn build_delete_head(&self, table_name: &str) -> String {
+        let table = self.table_ident(&self.sharded_table(table_name));
+        match &self.alias {
+            Some(alias) if self.uses_multi_table_delete() => {
+                let mut sql = format!("DELETE {} FROM {} AS {}", alias, table, alias);
+                self.push_joins(&mut sql, table_name);
+                sql
+            }
+            Some(alias) => format!("DELETE FROM {} AS {}", table, alias),
+            None => format!("DELETE FROM {}", table),
+        }
+    }
+
+    // 构建统计SQL，与 build_count_params 对应
+    pub fn build_count_sql(&self, table_name: &str) -> String {
+        if let Some(count_sql) = &self.count_sql_override {
+            let mut sql = count_sql.clone();
+            if self.count_sql_where {
+                self.append_where(&mut sql, true);
+            }
+            return sql;
+        }
+
+        let mut sql = self.build_with();
+        sql.push_str(&self.build_count_select(table_name));
+        sql
+    }
+
+    fn build_count_select(&self, table_name: &str) -> String {
+        if self.wraps_count() {
+            // 将 WHERE 条件、分组和 UNION 分支放入子查询内部
+            let inner_sql = self.build_compound(table_name, &self.select_list(), true);
+
+            // 包装成计数查询
+            format!("SELECT COUNT(*) FROM ({}) as t", inner_sql)
+        } else {
+            // 常规统计始终使用 COUNT(*)，不计算查询列
+            self.build_filter(table_name, "COUNT(*)", true)
+        }
+    }
+}
+
+// 可复用的条件集合，独立于查询构建，通过 QueryWrapper::apply 合并到多个 wrapper 中
+// 例: 同一个 Condition 同时用于查询、统计和删除
+#[derive(Default, Debug, Clone)]
+pub struct Condition {
+    inner: QueryWrapper,
+}
+
+impl Condition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 设置数据库方言，影响 like / ilike 等与方言相关的条件，应与使用它的 wrapper 一致
+    pub fn dialect(self, dialect: Dialect) -> Self {
+        self.map(|w| w.dialect(dialect))
+    }
+
+    pub fn eq<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.map(|w| w.eq(column, value))
+    }
+
+    pub fn ne<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.map(|w| w.ne(column, value))
+    }
+
+    pub fn gt<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.map(|w| w.gt(column, value))
+    }
+
+    pub fn lt<T: Into<Value>>(self, column: &str, value: T) -> Self {
+        self.map(|w| w.lt(column, value))
+    }
+
+    pub fn in_<T: Into<Value>>(self, column: &str, values: Vec<T>) -> Self {
+        self.map(|w| w.in_(column, values))
+    }
+
+    pub fn not_in<T: Into<Value>>(self, column: &str, values: Vec<T>) -> Self {
+        self.map(|w| w.not_in(column, values))
+    }
+
+    pub fn like(self, column: &str, value: &str) -> Self {
+        self.map(|w| w.like(column, value))
+    }
+
+    pub fn ilike(self, column: &str, value: &str) -> Self {
+        self.map(|w| w.ilike(column, value))
+    }
+
+    pub fn json_eq<T: Serialize>(self, column: &str, path: &str, value: T) -> Self {
+        self.map(|w| w.json_eq(column, path, value))
+    }
+
+    pub fn json_contains<T: Serialize>(self, column: &str, value: T) -> Self {
+        self.map(|w| w.json_contains(column, value))
+    }
+
+    pub fn where_raw(self, fragment: &str, params: Vec<Value>) -> Self {
+        self.map(|w| w.where_raw(fragment, params))
+    }
+
+    pub fn and_group<F>(self, f: F) -> Self
+    where
+        F: FnOnce(QueryWrapper) -> QueryWrapper,
+    {
+        self.map(|w| w.and_group(f))
+    }
+
+    pub fn or_group<F>(self, f: F) -> Self
+    where
+        F: FnOnce(QueryWrapper) -> QueryWrapper,
+    {
+        self.map(|w| w.or_group(f))
+    }
+
+    // 条件数量
+    pub fn len(&self) -> usize {
+        self.inner.where_conditions.len()
+    }
+
+    // 是否没有条件
+    pub fn is_empty(&self) -> bool {
+        self.inner.where_conditions.is_empty()
+    }
+
+    // 条件片段 (以 AND 连接) 和对应的绑定参数，便于单独测试过滤逻辑
+    pub fn build(&self) -> (String, Vec<Value>) {
+        (self.inner.where_conditions.join(" AND "), self.inner.where_params.clone())
+    }
+
+    fn map(self, f: impl FnOnce(QueryWrapper) -> QueryWrapper) -> Self {
+        Self { inner: f(self.inner) }
+    }
+}
+
+// page_window 模式下总数列的别名
+const WINDOW_TOTAL_COLUMN: &str = "__page_total";
+
+// 分页遍历器，由 iter_pages / iter_pages_by_key 创建，只在创建时复制一次 wrapper
+pub struct PageIter<'a, E: Executor + ?Sized> {
+    wrapper: QueryWrapper,
+    rb: &'a E,
+    table_name: String,
+    page_size: u64,
+    key: Option<(String, bool)>, // 游标列和排序方向
+    base_conditions: (usize, usize), // 游标条件之前的条件数和参数数
+    cursor: Option<Value>,
+    offset: u64,
+    done: bool,
+}
+
+impl<'a, E: Executor + ?Sized> PageIter<'a, E> {
+    fn new(wrapper: QueryWrapper, rb: &'a E, table_name: &str, page_size: u64, key: Option<(String, bool)>) -> Self {
+        let base_conditions = (wrapper.where_conditions.len(), wrapper.where_params.len());
+        Self {
+            wrapper,
+            rb,
+            table_name: table_name.to_string(),
+            page_size,
+            key,
+            base_conditions,
+            cursor: None,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    // 获取下一页数据，遍历结束后返回 None
+    pub async fn next_page<T>(&mut self) -> Result<Option<Vec<T>>, Error>
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de>,
+    {
+        if self.done {
+            return Ok(None);
+        }
+        if self.page_size == 0 {
+            return Err(Error::from("page_size must be greater than 0"));
+        }
+
+        let records = match &self.key {
+            Some((key_column, asc)) => {
+                // 去掉上一页的游标条件再添加新的游标
+                self.wrapper.where_conditions.truncate(self.base_conditions.0);
+                self.wrapper.where_params.truncate(self.base_conditions.1);
+                self.wrapper.apply_keyset(self.cursor.take(), self.page_size, key_column, *asc);
+                let (records, last_key, has_next) = self
+                    .wrapper
+                    .fetch_keyset(self.rb, &self.table_name, self.page_size, key_column)
+                    .await?;
+                self.cursor = last_key;
+                self.done = !has_next;
+                records
+            }
+            None => {
+                self.wrapper.limit = Some(self.page_size);
+                self.wrapper.offset = Some(self.offset);
+                let records: Vec<T> = self.wrapper.query(self.rb, &self.table_name).await?;
+                self.offset = self.offset.saturating_add(self.page_size);
+                self.done = (records.len() as u64) < self.page_size;
+                records
+            }
+        };
+
+        if records.is_empty() {
+            self.done = true;
+            return Ok(None);
+        }
+        Ok(Some(records))
+    }
+
+    // 转换为逐条返回记录的 Stream，当前批次消费完才查询下一批，提前丢弃 Stream 不会再发起查询
+    // 出错时返回该错误并结束
+    pub fn into_stream<T>(self) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: Serialize + for<'de> serde::Deserialize<'de> + 'a,
+    {
+        futures::stream::unfold((self, Vec::new().into_iter()), |(mut iter, mut batch)| async move {
+            loop {
+                if let Some(record) = batch.next() {
+                    return Some((Ok(record), (iter, batch)));
+                }
+                match iter.next_page::<T>().await {
+                    Ok(Some(records)) => batch = records.into_iter(),
+                    Ok(None) => return None,
+                    Err(e) => {
+                        iter.done = true;
+                        return Some((Err(e), (iter, batch)));
+                    }
+                }
+            }
+        })
+    }
+}
+
+// 临时性错误的重试策略，用于 QueryWrapper::retry
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,     // 最多执行次数，包含第一次执行
+    pub backoff: Duration,     // 第一次重试前的等待时间，之后每次翻倍
+    pub retry_on: Vec<String>, // 错误消息包含其中任一内容时重试 (不区分大小写)，为空时按 is_transient_error 判断
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(3, Duration::from_millis(50))
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            backoff,
+            retry_on: vec![],
+        }
+    }
+
+    // 添加需要重试的错误消息内容，如 "1213"、"lock wait timeout"
+    pub fn retry_on(mut self, pattern: &str) -> Self {
+        self.retry_on.push(pattern.to_string());
+        self
+    }
+
+    // 错误是否需要重试，超时错误的消息中包含 SQL，始终不重试
+    pub fn should_retry(&self, error: &Error) -> bool {
+        if is_timeout_error(error) {
+            return false;
+        }
+        if self.retry_on.is_empty() {
+            return is_transient_error(error);
+        }
+        let message = error.to_string().to_lowercase();
+        self.retry_on.iter().any(|pattern| message.contains(&pattern.to_lowercase()))
+    }
+}
+
+// timeout 超时错误的消息前缀
+const TIMEOUT_ERROR: &str = "query exceeded timeout";
+
+// 是否为 timeout 设置的执行超时错误
+pub fn is_timeout_error(error: &Error) -> bool {
+    error.to_string().starts_with(TIMEOUT_ERROR)
+}
+
+// get_one_required 没有记录时的错误消息前缀
+const NOT_FOUND_ERROR: &str = "record not found";
+
+// 是否为 get_one_required 没有找到记录的错误
+pub fn is_not_found_error(error: &Error) -> bool {
+    error.to_string().starts_with(NOT_FOUND_ERROR)
+}
+
+// 是否为可以重试的临时性错误 (死锁、锁等待超时、连接中断)，rbatis 的错误只有消息文本，按各数据库的错误码和消息判断
+pub fn is_transient_error(error: &Error) -> bool {
+    // 只匹配消息文本，不匹配数字错误码，避免唯一键冲突等消息中的数据被误判
+    const TRANSIENT: [&str; 10] = [
+        "deadlock",                   // MySQL 1213 / Postgres 40P01
+        "lock wait timeout",          // MySQL 1205
+        "could not serialize access", // Postgres 40001
+        "database is locked",         // SQLite
+        "connection reset",
+        "connection refused",
+        "connection closed",
+        "broken pipe",
+        "timed out",
+        "server has gone away",
+    ];
+    // 超时错误的消息中包含 SQL，不参与匹配
+    if is_timeout_error(error) {
+        return false;
+    }
+    let message = error.to_string().to_lowercase();
+    TRANSIENT.iter().any(|pattern| message.contains(pattern))
+}
+
+// 把实体序列化为 (列, 值)，跳过值为 None 的字段
+fn entity_columns<T: Serialize>(op: &str, entity: &T) -> Result<Vec<(String, Value)>, Error> {
+    let map = match rbs::to_value(entity) {
+        Ok(Value::Map(map)) => map,
+        Ok(value) => return Err(Error::from(format!("{} expects a struct or map, got {}", op, value))),
+        Err(e) => return Err(Error::from(format!("{} failed to serialize entity: {}", op, e))),
+    };
+    let mut columns = vec![];
+    for (column, value) in map {
+        match column {
+            _ if value.is_null() => {}
+            Value::String(column) => columns.push((column, value)),
+            column => return Err(Error::from(format!("{} field name must be a string, got {}", op, column))),
+        }
+    }
+    Ok(columns)
+}
+
+// 检查表名，表名无法作为参数绑定，只允许 [A-Za-z0-9_.] 组成的表名，后面可以跟一个别名 (users u / users AS u)
+// 空表名 (使用自定义SQL或 CTE 时) 不检查
+pub fn check_table_name(table_name: &str) -> Result<(), Error> {
+    fn is_ident(name: &str, allow_dot: bool) -> bool {
+        !name.is_empty()
+            && name.split('.').all(|part| !part.is_empty())
+            && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || (allow_dot && b == b'.'))
+    }
+    if table_name.is_empty() {
+        return Ok(());
+    }
+    let parts: Vec<&str> = table_name.split_whitespace().collect();
+    let valid = match parts.as_slice() {
+        [name] => is_ident(name, true),
+        [name, alias] => is_ident(name, true) && is_ident(alias, false),
+        [name, as_, alias] => is_ident(name, true) && as_.eq_ignore_ascii_case("AS") && is_ident(alias, false),
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::from(format!("invalid table name: {}", table_name)))
+    }
+}
+
+// 取结果集第一行第一列的值，没有数据时为 Null
+fn first_column(rows: Value) -> Value {
+    let row = match rows {
+        Value::Array(rows) => rows.into_iter().next().unwrap_or(Value::Null),
+        value => value,
+    };
+    match row {
+        Value::Map(row) => row.into_iter().next().map(|(_, value)| value).unwrap_or(Value::Null),
+        value => value,
+    }
+}
+
+// 取出只有一行一列的结果，行数或列数不是 1 时返回错误，用于校验自定义的统计 SQL
+fn single_column(rows: Value) -> Result<Value, Error> {
+    let mut rows = match rows {
+        Value::Array(rows) => rows,
+        value => vec![value],
+    };
+    if rows.len() != 1 {
+        return Err(Error::from(format!("expected exactly one row, got {}", rows.len())));
+    }
+    match rows.pop().unwrap() {
+        Value::Map(row) if row.len() == 1 => Ok(row.into_iter().next().map(|(_, value)| value).unwrap()),
+        Value::Map(row) => Err(Error::from(format!("expected exactly one column, got {}", row.len()))),
+        value => Ok(value),
+    }
+}
+
+// 把统计结果转换为 u64，不同驱动返回的 COUNT(*) 类型不同 (u64 / i64 / 数字字符串 / Decimal)
+fn decode_count(value: Value) -> Result<u64, Error> {
+    let invalid = |v: &Value| Error::from(format!("count result is not a non-negative number: {}", v));
+    match value {
+        Value::Null => Ok(0),
+        Value::U64(n) => Ok(n),
+        Value::U32(n) => Ok(n as u64),
+        Value::I64(n) => u64::try_from(n).map_err(|_| invalid(&Value::I64(n))),
+        Value::I32(n) => u64::try_from(n).map_err(|_| invalid(&Value::I32(n))),
+        Value::F64(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as u64),
+        Value::F32(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as u64),
+        Value::String(ref text) => {
+            // Decimal 等类型以字符串返回，允许 "5" 或 "5.0"
+            let text = text.trim();
+            let digits = text.strip_suffix(".0").unwrap_or(text);
+            digits.parse::<u64>().map_err(|_| invalid(&value))
+        }
+        Value::Ext(_, inner) => decode_count(*inner),
+        value => Err(invalid(&value)),
+    }
+}
+
+// 检查 limit / offset 是否在有符号 64 位整数范围内，部分驱动只接受 i64，超出时返回错误而不是生成驱动拒绝的 SQL
+fn check_page_bounds(limit: Option<u64>, offset: Option<u64>) -> Result<(), Error> {
+    let max = i64::MAX as u64;
+    if let Some(limit) = limit.filter(|&limit| limit > max) {
+        return Err(Error::from(format!("limit {} is out of range (max {})", limit, max)));
+    }
+    if let Some(offset) = offset.filter(|&offset| offset > max) {
+        return Err(Error::from(format!("offset {} is out of range (max {})", offset, max)));
+    }
+    Ok(())
+}
+
+// 转义 LIKE 的通配符，用户输入中的 % 和 _ 按普通字符匹配
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// 把点分隔的键转换为 MySQL/SQLite 的 JSON 路径，每个键都加引号，键中可以包含 - 等特殊字符
+fn json_path(keys: &[&str]) -> String {
+    let mut path = String::from("$");
+    for key in keys {
+        path.push_str(".\"");
+        path.push_str(&key.replace('\\', "\\\\").replace('"', "\\\""));
+        path.push('"');
+    }
+    path
+}
+
+// 取出一行中 key_column 列的值，结果集中的列名不带表别名前缀
+fn row_key<K: for<'de> serde::Deserialize<'de>>(row: &Value, key_column: &str) -> Result<K, Error> {
+    let key = key_column.rsplit('.').next().unwrap_or(key_column);
+    match row {
+        Value::Map(row) => match row.get(&Value::String(key.to_string())) {
+            Value::Null => Err(Error::from(format!("key column {} is missing from the result", key))),
+            value => Ok(rbs::from_value::<K>(value.clone())?),
+        },
+        _ => Err(Error::from(format!("key column {} is missing from the result", key))),
+    }
+}
+
+// 把一行数据按列顺序解码为元组，列数与元组元素个数不一致时返回带列名的错误
+// 结果集中重名的列 (如 a.id 和 b.id) 只会保留一个，需要用别名区分
+fn decode_tuple<T: for<'de> serde::Deserialize<'de>>(row: Value) -> Result<T, Error> {
+    let (columns, values): (Vec<String>, Vec<Value>) = match row {
+        Value::Map(row) => row.into_iter().map(|(key, value)| (key.as_str().unwrap_or_default().to_string(), value)).unzip(),
+        value => return rbs::from_value::<T>(value),
+    };
+    rbs::from_value::<T>(Value::Array(values)).map_err(|e| {
+        Error::from(format!(
+            "cannot decode row with {} column(s) [{}] into {}: {}",
+            columns.len(),
+            columns.join(", "),
+            std::any::type_name::<T>(),
+            e
+        ))
+    })
+}
+
+// 去掉 SQL 末尾最外层的 ORDER BY 子句，括号内 (子查询、窗口函数) 和引号内的 ORDER BY 不受影响
+// ORDER BY 之后还有 LIMIT / OFFSET / FETCH 时排序会影响结果行，保留原语句
+fn strip_order_by(sql: &str) -> &str {
+    // 只转换 ASCII 字符，保证下标与原语句一致
+    let upper = sql.to_ascii_uppercase();
+    let bytes = upper.as_bytes();
+    let mut depth = 0i32;
+    let mut quote: Option<u8> = None;
+    let mut last_order_by = None;
+    for (i, &b) in bytes.iter().enumerate() {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None => match b {
+                b'\'' | b'"' | b'`' => quote = Some(b),
+                b'(' => depth += 1,
+                b')' => depth -= 1,
+                b'O' if depth == 0
+                    && upper[i..].starts_with("ORDER")
+                    && (i == 0 || !is_word_byte(bytes[i - 1])) =>
+                {
+                    let rest = upper[i + 5..].trim_start();
+                    if rest.starts_with("BY") {
+                        last_order_by = Some(i);
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    match last_order_by {
+        Some(i) => {
+            let tail = &upper[i..];
+            if ["LIMIT", "OFFSET", "FETCH"].iter().any(|k| tail.contains(k)) {
+                sql
+            } else {
+                sql[..i].trim_end()
+            }
+        }
+        None => sql,
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    type Handler = Box<dyn Fn(&str, &[Value]) -> Result<Value, Error> + Send + Sync>;
+
+    // 记录执行的 SQL 和参数，查询结果由 handler 按语句生成，没有 handler 时返回空数组
+    // 设置 delay 时每条查询先等待 delay，并记录同时执行的查询数的最大值
+    #[derive(Default)]
+    struct MockExecutor {
+        statements: Mutex<Vec<(String, Vec<Value>)>>,
+        handler: Option<Handler>,
+        delay: Option<Duration>,
+        running: AtomicUsize,
+        max_running: AtomicUsize,
+    }
+
+    impl MockExecutor {
+        fn new(handler: impl Fn(&str, &[Value]) -> Result<Value, Error> + Send + Sync + 'static) -> Self {
+            Self {
+                handler: Some(Box::new(handler)),
+                ..Self::default()
+            }
+        }
+
+        fn with_delay(mut self, delay: Duration) -> Self {
+            self.delay = Some(delay);
+            self
+        }
+
+        fn statements(&self) -> Vec<(String, Vec<Value>)> {
+            self.statements.lock().unwrap().clone()
+        }
+
+        fn sqls(&self) -> Vec<String> {
+            self.statements().into_iter().map(|(sql, _)| sql).collect()
+        }
+    }
+
+    // 一列的结果行
+    fn row(column: &str, value: impl Into<Value>) -> Value {
+        let mut map = rbs::value::map::ValueMap::new();
+        map.insert(Value::from(column), value.into());
+        Value::Map(map)
+    }
+
+    // 从 SQL 末尾取出 LIMIT 的值
+    fn sql_limit(sql: &str) -> u64 {
+        let tail = sql.rsplit("LIMIT ").next().unwrap();
+        let count = tail.split(',').next_back().unwrap();
+        count.split_whitespace().next().unwrap().parse().unwrap()
+    }
+
+    // id 为 0..total 的数据表
+    fn id_table(total: u64) -> MockExecutor {
+        MockExecutor::new(move |sql, params| id_rows(total, sql, params))
+    }
+
+    // 统计查询返回 total，其它查询按 LIMIT offset, size / LIMIT size OFFSET offset 或游标条件 id > ? 返回对应的行，没有 LIMIT 时返回全部
+    fn id_rows(total: u64, sql: &str, params: &[Value]) -> Result<Value, Error> {
+        if sql.contains("COUNT(*)") {
+            return Ok(Value::Array(vec![row("count", total)]));
+        }
+        let (offset, size) = match sql.rsplit_once("LIMIT ") {
+            None => (0, total),
+            Some((_, tail)) => match (tail.split_once(", "), tail.split_once(" OFFSET ")) {
+                (Some((offset, size)), _) => (offset.parse().unwrap(), size.parse::<u64>().unwrap()),
+                (None, Some((size, offset))) => (offset.parse().unwrap(), size.parse().unwrap()),
+                (None, None) => (0, tail.parse().unwrap()),
+            },
+        };
+        let start = match sql.contains("id > ?") {
+            true => params.last().and_then(|v| v.as_u64()).unwrap() + 1,
+            false => offset,
+        };
+        let end = start.saturating_add(size).min(total);
+        Ok(Value::Array((start..end).map(|i| row("id", i)).collect()))
+    }
+
+    #[rbatis::async_trait]
+    impl Executor for MockExecutor {
+        async fn query(&self, sql: &str, params: Vec<Value>) -> Result<Value, Error> {
+            self.statements.lock().unwrap().push((sql.to_string(), params.clone()));
+            if let Some(delay) = self.delay {
+                let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_running.fetch_max(running, Ordering::SeqCst);
+                tokio::time::sleep(delay).await;
+                self.running.fetch_sub(1, Ordering::SeqCst);
+            }
+            match &self.handler {
+                Some(handler) => handler(sql, &params),
+                None => Ok(Value::Array(vec![])),
+            }
+        }
+
+        // 有 handler 时按其结果返回错误，或把返回的数值作为影响行数
+        async fn exec(&self, sql: &str, params: Vec<Value>) -> Result<ExecResult, Error> {
+            self.statements.lock().unwrap().push((sql.to_string(), params.clone()));
+            let rows_affected = match &self.handler {
+                Some(handler) => handler(sql, &params)?.as_u64().unwrap_or(0),
+                None => 0,
+            };
+            Ok(ExecResult {
+                rows_affected,
+                last_insert_id: Value::Null,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_requires_conditions() {
+        let rb = MockExecutor::default();
+        let err = QueryWrapper::new().delete(&rb, "users").await.unwrap_err();
+        assert!(err.to_string().contains("allow_delete_all"));
+        assert!(QueryWrapper::new().exec(&rb, "UPDATE users SET a = 1", vec![], "users").await.is_err());
+        assert!(rb.statements().is_empty());
+
+        QueryWrapper::new().allow_delete_all().delete(&rb, "users").await.unwrap();
+        QueryWrapper::new().eq("id", 1).delete(&rb, "users").await.unwrap();
+        let statements = rb.statements();
+        assert_eq!(statements[0].0, "DELETE FROM users");
+        assert_eq!(statements[1].0, "DELETE FROM users WHERE id = ?");
+    }
+
+    #[tokio::test]
+    async fn delete_rejects_custom_sql() {
+        let rb = MockExecutor::default();
+        let wrapper = QueryWrapper::new().custom_sql("SELECT * FROM users WHERE id = 5");
+        assert!(wrapper.clone().delete(&rb, "users").await.is_err());
+        assert!(wrapper.clone().delete_in_chunks(&rb, "users", 100).await.is_err());
+        assert!(wrapper.exec(&rb, "UPDATE users SET a = 1", vec![], "users").await.is_err());
+        assert!(rb.statements().is_empty());
+    }
+
+    #[tokio::test]
+    async fn max_limit_caps_page_size() {
+        let rb = MockExecutor::new(|sql, _| {
+            if sql.starts_with("SELECT COUNT") {
+                return Ok(Value::Array(vec![row("count", 1000)]));
+            }
+            Ok(Value::Array((0..sql_limit(sql)).map(|i| row("id", i)).collect()))
+        });
+        let page: Page<serde_json::Value> = QueryWrapper::new().max_limit(100).page(&rb, "t", 2, 500).await.unwrap();
+        assert_eq!(rb.sqls()[1], "SELECT * FROM t LIMIT 100, 100");
+        assert_eq!((page.page_size, page.pages, page.len()), (100, 10, 100));
+    }
+
+    #[tokio::test]
+    async fn max_limit_keeps_probe_row() {
+        let rb = MockExecutor::new(|sql, _| Ok(Value::Array((0..sql_limit(sql)).map(|i| row("id", i)).collect())));
+        let page: Page<serde_json::Value> = QueryWrapper::new()
+            .max_limit(100)
+            .search_count(false)
+            .page(&rb, "t", 1, 100)
+            .await
+            .unwrap();
+        assert_eq!(rb.sqls()[0], "SELECT * FROM t LIMIT 0, 101");
+        assert!(page.has_next);
+        assert_eq!(page.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn max_limit_keyset_visits_every_row() {
+        // 1000 行数据，id 从 0 开始
+        let rb = MockExecutor::new(|sql, params| {
+            let start = params.first().and_then(|v| v.as_u64()).map_or(0, |id| id + 1);
+            let end = (start + sql_limit(sql)).min(1000);
+            Ok(Value::Array((start..end).map(|i| row("id", i)).collect()))
+        });
+        let mut visited = 0;
+        QueryWrapper::new()
+            .max_limit(100)
+            .for_each_chunk(&rb, "t", 500, "id", |rows: Vec<serde_json::Value>| {
+                assert!(rows.len() <= 100);
+                visited += rows.len();
+                async { Ok(()) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(visited, 1000);
+    }
+
+    #[test]
+    fn sortable_columns_checks_every_order_term() {
+        let injected = "name; DROP TABLE t";
+        assert!(QueryWrapper::new().order_by(injected, true).sortable_columns(&["id"]).validate().is_err());
+        assert!(QueryWrapper::new().sortable_columns(&["id"]).order_by(injected, true).validate().is_err());
+        assert!(QueryWrapper::new().order_by_raw(injected).sortable_columns(&["id"]).validate().is_err());
+        assert!(QueryWrapper::new()
+            .order_by_nulls("name", true, NullsOrder::Last)
+            .sortable_columns(&["id"])
+            .validate()
+            .is_err());
+        assert!(QueryWrapper::new().order_by("id", false).sortable_columns(&["id"]).validate().is_ok());
+        assert!(QueryWrapper::new().order_by_random().sortable_columns(&["id"]).validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn skip_locked_only_on_select() {
+        let wrapper = QueryWrapper::new().eq("id", 1).for_update_skip_locked();
+        assert_eq!(wrapper.build_sql("jobs"), "SELECT * FROM jobs WHERE id = ? FOR UPDATE SKIP LOCKED");
+
+        let rb = MockExecutor::default();
+        wrapper.clone().delete(&rb, "jobs").await.unwrap();
+        wrapper.clone().delete_in_chunks(&rb, "jobs", 10).await.unwrap();
+        wrapper.exec(&rb, "UPDATE jobs SET state = 1", vec![], "jobs").await.unwrap();
+        assert!(rb.sqls().iter().all(|sql| !sql.contains("SKIP LOCKED")), "{:?}", rb.sqls());
+    }
+
+    #[test]
+    fn skip_locked_rejects_compound_queries() {
+        let locked = || QueryWrapper::new().for_update_skip_locked();
+        assert!(locked().union(QueryWrapper::new(), "b").validate().is_err());
+        assert!(locked().group_by(vec!["state"]).validate().is_err());
+        assert!(locked().page_window(true).validate().is_err());
+        assert!(locked().dialect(Dialect::Sqlite).validate().is_err());
+        assert!(locked().dialect(Dialect::Postgres).validate().is_ok());
+    }
+
+    #[test]
+    fn sub_wrapper_errors_propagate() {
+        let broken = || QueryWrapper::new().dialect(Dialect::Sqlite).json_contains("tags", vec!["a"]);
+        assert!(QueryWrapper::new().union(broken(), "b").validate().is_err());
+        assert!(QueryWrapper::new().with("x", &broken(), "b").validate().is_err());
+        assert!(QueryWrapper::new().from_subquery(&broken(), "b", "s").validate().is_err());
+        assert!(QueryWrapper::new().left_join_subquery(&broken(), "b", "s", "s.id = a.id").validate().is_err());
+        assert!(QueryWrapper::new().select_count_as(&broken(), "b", "n").validate().is_err());
+        assert!(QueryWrapper::new()
+            .strict_mode(true)
+            .union(QueryWrapper::new().in_::<i64>("id", vec![]), "b")
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn sqlite_union_branches_are_not_parenthesized() {
+        let sqlite = || QueryWrapper::new().dialect(Dialect::Sqlite);
+        let wrapper = sqlite().eq("a", 1).union_all(sqlite().eq("b", 2), "t2").order_by("id", true);
+        assert_eq!(
+            wrapper.build_sql("t1"),
+            "SELECT * FROM t1 WHERE a = ? UNION ALL SELECT * FROM t2 WHERE b = ? ORDER BY id ASC"
+        );
+        assert_eq!(
+            wrapper.build_count_sql("t1"),
+            "SELECT COUNT(*) FROM (SELECT * FROM t1 WHERE a = ? UNION ALL SELECT * FROM t2 WHERE b = ?) as t"
+        );
+        assert!(wrapper.validate().is_ok());
+        assert!(sqlite().union(sqlite().limit(5), "t2").validate().is_err());
+        assert_eq!(
+            QueryWrapper::new().union(QueryWrapper::new(), "t2").build_sql("t1"),
+            "(SELECT * FROM t1) UNION (SELECT * FROM t2)"
+        );
+    }
+
+    #[test]
+    fn dialect_must_be_set_before_dialect_specific_terms() {
+        let sql = QueryWrapper::new().dialect(Dialect::Sqlite).like("name", "50%").build_sql("t");
+        assert!(sql.contains("ESCAPE '\\'"), "{}", sql);
+        assert!(QueryWrapper::new().like("name", "50%").dialect(Dialect::Sqlite).validate().is_err());
+        assert!(QueryWrapper::new().order_by_random().dialect(Dialect::Postgres).validate().is_err());
+        assert!(QueryWrapper::new().eq("id", 1).dialect(Dialect::Postgres).validate().is_ok());
+
+        let mysql_terms = Condition::new().ilike("name", "a%");
+        assert!(QueryWrapper::new().dialect(Dialect::Postgres).apply(&mysql_terms).validate().is_err());
+        let pg_terms = Condition::new().dialect(Dialect::Postgres).ilike("name", "a%");
+        let sql = QueryWrapper::new().dialect(Dialect::Postgres).apply(&pg_terms).build_sql("t");
+        assert!(sql.contains("ILIKE"), "{}", sql);
+    }
+
+    #[tokio::test]
+    async fn single_row_getters_limit_to_one_row() {
+        let rb = MockExecutor::new(|_, _| Ok(Value::Array(vec![row("id", Value::I64(1)), row("id", Value::I64(2))])));
+        let wrapper = QueryWrapper::new().eq("status", 1);
+        let json = wrapper.get_one_json(&rb, "t").await.unwrap().unwrap();
+        assert_eq!(json["id"], 1);
+        let first: Option<serde_json::Value> = wrapper.get_first_of_many(&rb, "t").await.unwrap();
+        assert!(first.is_some());
+        let limited: Option<serde_json::Value> = wrapper.clone().limit(5).get_first_of_many(&rb, "t").await.unwrap();
+        assert!(limited.is_some());
+        let limits: Vec<u64> = rb.sqls().iter().map(|sql| sql_limit(sql)).collect();
+        assert_eq!(limits, vec![1, 1, 5]);
+    }
+
+    #[test]
+    fn estimate_uses_sharded_table() {
+        let (_, params) = QueryWrapper::new().table_suffix("_202401").build_estimate_sql("orders o").unwrap();
+        assert_eq!(params, vec![Value::from("orders_202401")]);
+        let (_, params) = QueryWrapper::new()
+            .dialect(Dialect::Postgres)
+            .table_suffix("_1")
+            .build_estimate_sql("app.orders")
+            .unwrap();
+        assert_eq!(params, vec![Value::from("app.orders_1")]);
+    }
+
+    #[tokio::test]
+    async fn count_override_must_return_one_column() {
+        let wrapper = QueryWrapper::new().count_sql("SELECT n FROM t_stats");
+        let rb = MockExecutor::new(|_, _| Ok(Value::Array(vec![row("n", 7)])));
+        assert_eq!(wrapper.count(&rb, "t").await.unwrap(), 7);
+
+        let rb = MockExecutor::new(|_, _| {
+            let mut map = rbs::value::map::ValueMap::new();
+            map.insert(Value::from("n"), Value::I64(7));
+            map.insert(Value::from("m"), Value::I64(8));
+            Ok(Value::Array(vec![Value::Map(map)]))
+        });
+        let err = wrapper.count(&rb, "t").await.unwrap_err();
+        assert!(err.to_string().contains("count_sql override"), "{}", err);
+
+        let rb = MockExecutor::new(|_, _| Ok(Value::Array(vec![])));
+        assert!(wrapper.count(&rb, "t").await.is_err());
+        assert_eq!(QueryWrapper::new().count(&rb, "t").await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn aliased_delete_forms() {
+        let rb = MockExecutor::default();
+        let wrapper = QueryWrapper::new().alias("m").eq("m.status", 0);
+        wrapper.clone().order_by("m.id", true).limit(100).delete(&rb, "member").await.unwrap();
+        wrapper
+            .clone()
+            .inner_join("orders o", "o.member_id = m.id")
+            .delete(&rb, "member")
+            .await
+            .unwrap();
+        let joined_limit = wrapper
+            .inner_join("orders o", "o.member_id = m.id")
+            .limit(100)
+            .delete(&rb, "member")
+            .await;
+        assert!(joined_limit.is_err());
+        assert_eq!(
+            rb.sqls(),
+            vec![
+                "DELETE FROM member AS m WHERE m.status = ? ORDER BY m.id ASC LIMIT 100",
+                "DELETE m FROM member AS m INNER JOIN orders o ON o.member_id = m.id WHERE m.status = ?",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_one_required_not_found() {
+        let rb = MockExecutor::default();
+        #[derive(Debug, Serialize, serde::Deserialize)]
+        struct User {
+            id: i64,
+        }
+        let err = QueryWrapper::new().eq("id", 1).get_one_required::<User>(&rb, "t").await.unwrap_err();
+        assert!(is_not_found_error(&err), "{}", err);
+        assert!(!is_timeout_error(&err));
+        assert!(!is_not_found_error(&Error::from("connection reset")));
+    }
+
+    #[tokio::test]
+    async fn page_size_and_page_no_zero() {
+        let page = Page::new(vec![1, 2], 25, 1, 0);
+        assert_eq!((page.pages, page.has_next, page.has_prev), (0, false, false));
+        let page = Page::new(vec![1, 2], 25, 0, 10);
+        assert_eq!((page.pages, page.has_next, page.has_prev), (3, true, false));
+        let page = Page::<i32>::new(vec![], 25, 0, 0);
+        assert_eq!((page.pages, page.has_next, page.has_prev, page.current_size), (0, false, false, 0));
+
+        let rb = MockExecutor::new(|sql, _| {
+            if sql.contains("COUNT(*)") {
+                return Ok(Value::Array(vec![row("count", 25)]));
+            }
+            Ok(Value::Array((0..sql_limit(sql)).map(|i| row("id", i)).collect()))
+        });
+        let wrapper = QueryWrapper::new();
+        assert!(wrapper.page::<serde_json::Value>(&rb, "t", 1, 0).await.is_err());
+        assert!(wrapper.page::<serde_json::Value>(&rb, "t", 0, 0).await.is_err());
+        let page = wrapper.page::<serde_json::Value>(&rb, "t", 0, 10).await.unwrap();
+        assert_eq!((page.page_no, page.current_size), (1, 10));
+        assert!(rb.sqls().last().unwrap().ends_with("LIMIT 0, 10"), "{:?}", rb.sqls());
+    }
+
+    #[tokio::test]
+    async fn decode_count_shapes() {
+        assert_eq!(decode_count(Value::U64(5)).unwrap(), 5);
+        assert_eq!(decode_count(Value::U32(5)).unwrap(), 5);
+        assert_eq!(decode_count(Value::I64(5)).unwrap(), 5);
+        assert_eq!(decode_count(Value::I32(5)).unwrap(), 5);
+        assert_eq!(decode_count(Value::F64(5.0)).unwrap(), 5);
+        assert_eq!(decode_count(Value::from("5")).unwrap(), 5);
+        assert_eq!(decode_count(Value::from("5.0")).unwrap(), 5);
+        assert_eq!(decode_count(Value::Ext("Decimal", Box::new(Value::from("5")))).unwrap(), 5);
+        assert_eq!(decode_count(Value::Null).unwrap(), 0);
+        assert!(decode_count(Value::I64(-1)).is_err());
+        assert!(decode_count(Value::F64(1.5)).is_err());
+        assert!(decode_count(Value::from("five")).is_err());
+        assert!(decode_count(Value::Bool(true)).is_err());
+
+        // 驱动返回标量、单行单列或多行结果时都取第一行第一列
+        assert_eq!(first_column(Value::I64(5)), Value::I64(5));
+        assert_eq!(first_column(Value::Array(vec![row("COUNT(*)", 5)])), Value::I64(5));
+        assert_eq!(first_column(Value::Array(vec![])), Value::Null);
+
+        for shape in [Value::Array(vec![row("COUNT(*)", Value::I64(7))]), Value::Array(vec![row("count", "7")]), Value::U64(7)] {
+            let rb = MockExecutor::new(move |_, _| Ok(shape.clone()));
+            assert_eq!(QueryWrapper::new().count(&rb, "t").await.unwrap(), 7);
+        }
+    }
+
+    #[test]
+    fn strip_order_by_only_removes_the_outer_clause() {
+        assert_eq!(strip_order_by("SELECT * FROM t ORDER BY id DESC"), "SELECT * FROM t");
+        assert_eq!(
+            strip_order_by("SELECT * FROM (SELECT * FROM t ORDER BY id LIMIT 10) s"),
+            "SELECT * FROM (SELECT * FROM t ORDER BY id LIMIT 10) s"
+        );
+        assert_eq!(
+            strip_order_by("SELECT * FROM (SELECT id FROM t ORDER BY id) s ORDER BY s.id"),
+            "SELECT * FROM (SELECT id FROM t ORDER BY id) s"
+        );
+        assert_eq!(
+            strip_order_by("SELECT id, ROW_NUMBER() OVER (PARTITION BY g ORDER BY id) rn FROM t"),
+            "SELECT id, ROW_NUMBER() OVER (PARTITION BY g ORDER BY id) rn FROM t"
+        );
+        assert_eq!(
+            strip_order_by("SELECT id, ROW_NUMBER() OVER (ORDER BY id) rn FROM t order by rn"),
+            "SELECT id, ROW_NUMBER() OVER (ORDER BY id) rn FROM t"
+        );
+        assert_eq!(strip_order_by("SELECT 'ORDER BY' AS s FROM t"), "SELECT 'ORDER BY' AS s FROM t");
+        assert_eq!(strip_order_by("SELECT * FROM t ORDER BY id LIMIT 5"), "SELECT * FROM t ORDER BY id LIMIT 5");
+        assert_eq!(strip_order_by("SELECT border_by FROM t"), "SELECT border_by FROM t");
+
+        let wrapper = QueryWrapper::new()
+            .custom_sql("SELECT * FROM (SELECT id FROM t ORDER BY id) s ORDER BY s.id")
+            .eq("s.id", 1);
+        assert_eq!(
+            wrapper.build_count_sql("t"),
+            "SELECT COUNT(*) FROM (SELECT * FROM (SELECT id FROM t ORDER BY id) s WHERE s.id = ?) as t"
+        );
+    }
+
+    #[test]
+    fn count_and_data_share_where() {
+        fn where_clause(sql: &str) -> &str {
+            let start = sql.find(" WHERE ").expect(sql) + 7;
+            let rest = &sql[start..];
+            let end = [" ORDER BY", " LIMIT", ") as t"].iter().filter_map(|k| rest.find(k)).min().unwrap_or(rest.len());
+            &rest[..end]
+        }
+        let conditions = |w: QueryWrapper| {
+            w.gt("age", 18)
+                .lt("age", 60)
+                .in_("status", vec![1, 2])
+                .or_group(|w| w.eq("role", "admin").eq("role", "owner"))
+                .where_raw("created_at >= ?", vec![Value::from("2024-01-01")])
+                .order_by("id", false)
+                .limit(10)
+        };
+        for wrapper in [
+            conditions(QueryWrapper::new()),
+            conditions(QueryWrapper::new().custom_sql("SELECT m.* FROM member m")),
+        ] {
+            let data_sql = wrapper.build_sql("member");
+            let count_sql = wrapper.build_count_sql("member");
+            assert_eq!(where_clause(&data_sql), where_clause(&count_sql), "{} / {}", data_sql, count_sql);
+            assert_eq!(
+                where_clause(&data_sql),
+                "age > ? AND age < ? AND status IN (?, ?) AND (role = ? OR role = ?) AND (created_at >= ?)"
+            );
+            assert_eq!(wrapper.build_params(), wrapper.build_count_params());
+        }
+    }
+
+    #[tokio::test]
+    async fn page_overflow_policies() {
+        // 共 25 条记录，每页 10 条，共 3 页
+        let rb = id_table(25);
+        let shape = |page: &Page<serde_json::Value>| {
+            let first = page.records.first().map(|r| r["id"].as_u64().unwrap());
+            (page.page_no, page.current_size, page.pages, page.has_next, page.has_prev, first)
+        };
+        let first_page = (1, 10, 3, true, false, Some(0));
+        let last_page = (3, 5, 3, false, true, Some(20));
+
+        let empty = QueryWrapper::new();
+        let cases = [
+            (0, first_page),
+            (1, first_page),
+            (3, last_page),
+            (4, (4, 0, 3, false, true, None)),
+            (99, (99, 0, 3, false, true, None)),
+        ];
+        for (page_no, expected) in cases {
+            assert_eq!(shape(&empty.page(&rb, "t", page_no, 10).await.unwrap()), expected, "Empty, page_no = {}", page_no);
+        }
+        // Empty 策略下超出的页码不查询数据
+        let data_queries = || rb.sqls().iter().filter(|sql| !sql.contains("COUNT(*)")).count();
+        assert_eq!(data_queries(), 3);
+
+        let last = QueryWrapper::new().page_overflow(PageOverflow::Last);
+        let cases = [(0, first_page), (1, first_page), (3, last_page), (4, last_page), (99, last_page)];
+        for (page_no, expected) in cases {
+            assert_eq!(shape(&last.page(&rb, "t", page_no, 10).await.unwrap()), expected, "Last, page_no = {}", page_no);
+        }
+        assert_eq!(data_queries(), 8);
+    }
+
+    #[test]
+    fn page_constructors_edge_cases() {
+        let shape = |page: &Page<i32>| (page.records.clone(), page.total, page.pages, page.has_next, page.has_prev);
+
+        let page = Page::<i32>::empty(2, 10);
+        assert_eq!(shape(&page), (vec![], 0, 0, false, true));
+        assert_eq!(page.page_no, 2);
+
+        assert_eq!(shape(&Page::from_full_list(vec![], 1, 10)), (vec![], 0, 0, false, false));
+        let all: Vec<i32> = (1..=20).collect();
+        // 总数正好是 page_size 的整数倍
+        assert_eq!(shape(&Page::from_full_list(all.clone(), 2, 10)), ((11..=20).collect(), 20, 2, false, true));
+        assert_eq!(shape(&Page::from_full_list(all.clone(), 1, 10)), ((1..=10).collect(), 20, 2, true, false));
+        // 超出最后一页
+        assert_eq!(shape(&Page::from_full_list(all.clone(), 3, 10)), (vec![], 20, 2, false, true));
+        assert_eq!(shape(&Page::from_full_list(all.clone(), u64::MAX, 10)), (vec![], 20, 2, false, true));
+        // 页码 0 按第一页处理
+        let page = Page::from_full_list(all.clone(), 0, 7);
+        assert_eq!(shape(&page), ((1..=7).collect(), 20, 3, true, false));
+        assert_eq!(page.page_no, 1);
+        assert_eq!(Page::from_full_list(all, 3, 7).current_size, 6);
+    }
+
+    #[test]
+    fn page_json_snapshot() {
+        let json = serde_json::to_string(&Page::new(vec![1], 25, 2, 10)).unwrap();
+        if cfg!(feature = "camel-case") {
+            assert_eq!(
+                json,
+                r#"{"records":[1],"currentSize":1,"total":25,"pageNo":2,"pageSize":10,"pages":3,"hasNext":true,"hasPrev":true,"totalIsEstimate":false}"#
+            );
+        } else {
+            assert_eq!(
+                json,
+                r#"{"records":[1],"current_size":1,"total":25,"page_no":2,"page_size":10,"pages":3,"has_next":true,"has_prev":true,"total_is_estimate":false}"#
+            );
+        }
+        let page: Page<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!((page.page_no, page.has_prev), (2, true));
+    }
+
+    #[test]
+    fn try_convert_propagates_first_error() {
+        #[derive(Debug, PartialEq)]
+        struct Positive(u32);
+        impl TryFrom<i64> for Positive {
+            type Error = String;
+            fn try_from(value: i64) -> Result<Self, Self::Error> {
+                u32::try_from(value).map(Positive).map_err(|_| format!("bad row {}", value))
+            }
+        }
+
+        let page: Page<Positive> = Page::new(vec![1, 2], 12, 2, 2).try_convert().unwrap();
+        assert_eq!(page.records, vec![Positive(1), Positive(2)]);
+        assert_eq!((page.total, page.page_no, page.pages, page.has_next), (12, 2, 6, true));
+
+        let err = Page::new(vec![1, -2, -3], 3, 1, 3).try_convert::<Positive>().unwrap_err();
+        assert_eq!(err, "bad row -2");
+    }
+
+    #[test]
+    fn order_by_values_per_dialect() {
+        let sql = |dialect| {
+            QueryWrapper::new()
+                .dialect(dialect)
+                .order_by_values("code", vec!["b", "a'1", "c\\d"], true)
+                .build_sql("t")
+        };
+        assert_eq!(sql(Dialect::MySql), "SELECT * FROM t ORDER BY FIELD(code, 'b', 'a''1', 'c\\\\d') ASC");
+        let case = "SELECT * FROM t ORDER BY CASE code WHEN 'b' THEN 0 WHEN 'a''1' THEN 1 WHEN 'c\\d' THEN 2 ELSE 3 END ASC";
+        assert_eq!(sql(Dialect::Postgres), case);
+        assert_eq!(sql(Dialect::Sqlite), case);
+        assert_eq!(
+            QueryWrapper::new().order_by_field("id", &[3, 1, 2]).build_sql("t"),
+            "SELECT * FROM t ORDER BY FIELD(id, '3', '1', '2')"
+        );
+        let empty: Vec<&str> = vec![];
+        assert_eq!(QueryWrapper::new().order_by_values("code", empty, true).build_sql("t"), "SELECT * FROM t");
+    }
+
+    #[test]
+    fn order_by_nulls_per_dialect() {
+        let sql = |dialect| {
+            QueryWrapper::new()
+                .dialect(dialect)
+                .order_by_nulls("last_login", false, NullsOrder::Last)
+                .order_by("id", true)
+                .build_sql("t")
+        };
+        // MySQL 没有 NULLS LAST，用 ISNULL 模拟，多出一个排序项
+        assert_eq!(sql(Dialect::MySql), "SELECT * FROM t ORDER BY ISNULL(last_login) ASC, last_login DESC, id ASC");
+        assert_eq!(sql(Dialect::Postgres), "SELECT * FROM t ORDER BY last_login DESC NULLS LAST, id ASC");
+        assert_eq!(sql(Dialect::Sqlite), "SELECT * FROM t ORDER BY last_login DESC NULLS LAST, id ASC");
+        assert_eq!(
+            QueryWrapper::new().order_by_nulls("last_login", true, NullsOrder::First).build_sql("t"),
+            "SELECT * FROM t ORDER BY ISNULL(last_login) DESC, last_login ASC"
+        );
+    }
+
+    #[test]
+    fn offset_without_limit_per_dialect() {
+        let sql = |dialect| QueryWrapper::new().dialect(dialect).offset(20).build_sql("t");
+        assert_eq!(sql(Dialect::MySql), "SELECT * FROM t LIMIT 20, 18446744073709551615");
+        assert_eq!(sql(Dialect::Postgres), "SELECT * FROM t OFFSET 20");
+        assert_eq!(sql(Dialect::Sqlite), "SELECT * FROM t LIMIT -1 OFFSET 20");
+        assert_eq!(
+            QueryWrapper::new().dialect(Dialect::Postgres).limit(5).offset(20).build_sql("t"),
+            "SELECT * FROM t LIMIT 5 OFFSET 20"
+        );
+        // 设置了 max_limit 时使用上限作为 LIMIT
+        assert_eq!(QueryWrapper::new().max_limit(100).offset(20).build_sql("t"), "SELECT * FROM t LIMIT 20, 100");
+    }
+
+    #[tokio::test]
+    async fn concurrent_page_overlaps_count_and_records() {
+        let handler = |sql: &str, _: &[Value]| {
+            if sql.contains("COUNT(*)") {
+                return Ok(Value::Array(vec![row("count", 25)]));
+            }
+            Ok(Value::Array((0..sql_limit(sql)).map(|i| row("id", i)).collect()))
+        };
+
+        let rb = MockExecutor::new(handler).with_delay(Duration::from_millis(20));
+        let page: Page<serde_json::Value> = QueryWrapper::new().page(&rb, "t", 1, 10).await.unwrap();
+        assert_eq!((page.total, page.current_size), (25, 10));
+        assert_eq!(rb.max_running.load(Ordering::SeqCst), 1);
+
+        let rb = MockExecutor::new(handler).with_delay(Duration::from_millis(20));
+        let page: Page<serde_json::Value> = QueryWrapper::new().concurrent_page(true).page(&rb, "t", 1, 10).await.unwrap();
+        assert_eq!((page.total, page.current_size), (25, 10));
+        assert_eq!(rb.max_running.load(Ordering::SeqCst), 2);
+
+        // 超出总页数时按 page_overflow 处理，Last 策略会再查询一次最后一页
+        let rb = MockExecutor::new(handler);
+        let page: Page<serde_json::Value> = QueryWrapper::new()
+            .concurrent_page(true)
+            .page_overflow(PageOverflow::Last)
+            .page(&rb, "t", 9, 10)
+            .await
+            .unwrap();
+        assert_eq!((page.page_no, page.pages), (3, 3));
+        assert_eq!(rb.sqls().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn timeout_returns_error_with_sql() {
+        let rb = MockExecutor::default().with_delay(Duration::from_millis(200));
+        let wrapper = QueryWrapper::new()
+            .eq("id", 1)
+            .timeout(Duration::from_millis(20))
+            .retry(RetryPolicy::new(3, Duration::from_millis(1)));
+        let err = wrapper.query::<serde_json::Value>(&rb, "t").await.unwrap_err();
+        assert!(is_timeout_error(&err), "{}", err);
+        assert!(err.to_string().contains("SELECT * FROM t WHERE id = ?"), "{}", err);
+        // 超时错误不重试
+        assert_eq!(rb.sqls().len(), 1);
+
+        let rb = MockExecutor::default().with_delay(Duration::from_millis(1));
+        let rows = QueryWrapper::new().timeout(Duration::from_secs(5)).query::<serde_json::Value>(&rb, "t").await.unwrap();
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn select_replaces_subquery_params() {
+        let sub = QueryWrapper::new().eq("o.status", 9).where_raw("o.user_id = u.id", vec![]);
+        let after = QueryWrapper::new()
+            .select(vec!["u.id"])
+            .select_count_as(&sub, "orders o", "n")
+            .eq("u.id", 5);
+        assert_eq!(
+            after.build_sql("users u"),
+            "SELECT u.id, (SELECT COUNT(*) FROM orders o WHERE o.status = ? AND (o.user_id = u.id)) AS n FROM users u WHERE u.id = ?"
+        );
+        assert_eq!(after.build_params(), vec![Value::I64(9), Value::I64(5)]);
+
+        let before = QueryWrapper::new()
+            .select_count_as(&sub, "orders o", "n")
+            .select(vec!["u.id"])
+            .eq("u.id", 5);
+        assert_eq!(before.build_sql("users u"), "SELECT u.id FROM users u WHERE u.id = ?");
+        assert_eq!(before.build_params(), vec![Value::I64(5)]);
+    }
+
+    #[test]
+    fn exists_sql_shapes() {
+        let plain = QueryWrapper::new().eq("a", 1);
+        assert_eq!(plain.build_exists_sql("t"), "SELECT EXISTS(SELECT 1 FROM t WHERE a = ? LIMIT 1)");
+        assert_eq!(plain.build_exists_params(), vec![Value::I64(1)]);
+
+        let union = QueryWrapper::new()
+            .eq("a", 1)
+            .union(QueryWrapper::new().eq("b", 2), "t2");
+        assert_eq!(
+            union.build_exists_sql("t1"),
+            "SELECT EXISTS(SELECT 1 FROM ((SELECT * FROM t1 WHERE a = ?) UNION (SELECT * FROM t2 WHERE b = ?)) t)"
+        );
+        assert_eq!(union.build_exists_params(), vec![Value::I64(1), Value::I64(2)]);
+
+        let custom = QueryWrapper::new().custom_sql("SELECT * FROM t ORDER BY id LIMIT 5");
+        assert_eq!(
+            custom.build_exists_sql("t"),
+            "SELECT EXISTS(SELECT 1 FROM (SELECT * FROM t ORDER BY id LIMIT 5) t)"
+        );
+    }
+
+    #[tokio::test]
+    async fn delete_rejects_select_only_clauses() {
+        let rb = MockExecutor::default();
+        let base = || QueryWrapper::new().eq("a", 1);
+        let wrappers = [
+            base().union(QueryWrapper::new(), "t2"),
+            base().group_by(vec!["a"]),
+            base().having("COUNT(*) > ?", vec![Value::from(1)]),
+            base().dialect(Dialect::Postgres).distinct_on(vec!["a"]),
+            base().from_subquery(&QueryWrapper::new(), "t2", "s"),
+        ];
+        for wrapper in wrappers {
+            let err = wrapper.clone().delete(&rb, "t").await.unwrap_err();
+            assert!(err.to_string().contains("only applies to queries"), "{}", err);
+            assert!(wrapper.clone().delete_in_chunks(&rb, "t", 10).await.is_err());
+            assert!(wrapper.exec(&rb, "UPDATE t SET b = 1", vec![], "t").await.is_err());
+        }
+        assert!(rb.statements().is_empty());
+    }
+
+    #[derive(Serialize)]
+    struct UserPatch {
+        name: Option<String>,
+        status: Option<i32>,
+        email: Option<String>,
+    }
+
+    #[tokio::test]
+    async fn update_sets_non_null_entity_fields() {
+        let rb = MockExecutor::default();
+        let patch = UserPatch { name: Some("alice".to_string()), status: None, email: Some("a@b.c".to_string()) };
+        let wrapper = QueryWrapper::new().eq("id", 7).set_entity(&patch).set("updated_at", "2024-01-01");
+        wrapper.update(&rb, "users").await.unwrap();
+        assert_eq!(
+            rb.statements(),
+            vec![(
+                "UPDATE users SET name = ?, email = ?, updated_at = ? WHERE id = ?".to_string(),
+                vec![Value::from("alice"), Value::from("a@b.c"), Value::from("2024-01-01"), Value::I64(7)],
+            )]
+        );
+
+        // 同一列以最后设置的为准，别名、分表后缀和 LIMIT 与 delete 一致
+        let wrapper = QueryWrapper::new()
+            .alias("u")
+            .table_suffix("_01")
+            .set("name", "bob")
+            .set_entity(&patch)
+            .eq("u.status", 0)
+            .limit(10);
+        assert_eq!(wrapper.build_update_sql("users"), "UPDATE users_01 AS u SET name = ?, email = ? WHERE u.status = ? LIMIT 10");
+        assert_eq!(wrapper.build_update_params(), vec![Value::from("alice"), Value::from("a@b.c"), Value::I64(0)]);
+    }
+
+    #[tokio::test]
+    async fn update_rejections() {
+        let rb = MockExecutor::default();
+        let patch = UserPatch { name: Some("alice".to_string()), status: None, email: None };
+        let empty = UserPatch { name: None, status: None, email: None };
+        let cases = [
+            (QueryWrapper::new().set_entity(&patch), "without conditions"),
+            (QueryWrapper::new().eq("id", 1).set_entity(&empty), "no columns to set"),
+            (QueryWrapper::new().eq("id", 1).set_entity(&1), "expects a struct or map"),
+            (QueryWrapper::new().eq("id", 1).set_entity(&patch).group_by(vec!["id"]), "only applies to queries"),
+            (QueryWrapper::new().custom_sql("SELECT * FROM users WHERE id = 1").set_entity(&patch), "custom_sql"),
+            (QueryWrapper::new().inner_join("t2", "t2.id = users.id").eq("id", 1).set("a", 1), "joins"),
+        ];
+        for (wrapper, message) in cases {
+            let err = wrapper.update(&rb, "users").await.unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+        assert!(rb.statements().is_empty());
+
+        QueryWrapper::new().allow_delete_all().set("status", 0).update(&rb, "users").await.unwrap();
+        assert_eq!(rb.sqls(), vec!["UPDATE users SET status = ?".to_string()]);
+    }
+
+    #[test]
+    fn upsert_sql_per_dialect() {
+        let columns = ["id", "name", "status"];
+        let build = |dialect, wrapper: QueryWrapper| wrapper.dialect(dialect).build_upsert_sql("users", &columns);
+        let on_id = || QueryWrapper::new().on_conflict(vec!["id"]);
+
+        assert_eq!(
+            build(Dialect::Postgres, on_id()),
+            "INSERT INTO users (id, name, status) VALUES (?, ?, ?) ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, status = EXCLUDED.status"
+        );
+        assert_eq!(
+            build(Dialect::Sqlite, QueryWrapper::new().on_conflict(vec!["tenant_id", "id"]).do_update(vec!["status"])),
+            "INSERT INTO users (id, name, status) VALUES (?, ?, ?) ON CONFLICT (tenant_id, id) DO UPDATE SET status = EXCLUDED.status"
+        );
+        assert_eq!(
+            build(Dialect::Postgres, on_id().do_nothing()),
+            "INSERT INTO users (id, name, status) VALUES (?, ?, ?) ON CONFLICT (id) DO NOTHING"
+        );
+        assert_eq!(
+            build(Dialect::MySql, on_id()),
+            "INSERT INTO users (id, name, status) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE name = VALUES(name), status = VALUES(status)"
+        );
+        assert_eq!(
+            build(Dialect::MySql, on_id().do_update(vec!["status"])),
+            "INSERT INTO users (id, name, status) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE status = VALUES(status)"
+        );
+        assert_eq!(
+            build(Dialect::MySql, QueryWrapper::new().do_nothing()),
+            "INSERT INTO users (id, name, status) VALUES (?, ?, ?) ON DUPLICATE KEY UPDATE id = id"
+        );
+        assert_eq!(
+            build(Dialect::Postgres, on_id().quote_table_name(true).table_suffix("_01")),
+            "INSERT INTO \"users_01\" (id, name, status) VALUES (?, ?, ?) ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name, status = EXCLUDED.status"
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_binds_non_null_entity_fields() {
+        #[derive(Serialize)]
+        struct User {
+            id: i64,
+            name: Option<String>,
+            status: Option<i32>,
+        }
+        let rb = MockExecutor::default();
+        let user = User { id: 1, name: Some("alice".to_string()), status: None };
+        let wrapper = QueryWrapper::new().dialect(Dialect::Postgres).on_conflict(vec!["id"]);
+        wrapper.upsert(&rb, "users", &user).await.unwrap();
+        assert_eq!(
+            rb.statements(),
+            vec![(
+                "INSERT INTO users (id, name) VALUES (?, ?) ON CONFLICT (id) DO UPDATE SET name = EXCLUDED.name".to_string(),
+                vec![Value::I64(1), Value::from("alice")],
+            )]
+        );
+
+        let rejected = [
+            (QueryWrapper::new().eq("id", 1), "does not accept conditions"),
+            (QueryWrapper::new().dialect(Dialect::Sqlite), "requires on_conflict columns"),
+        ];
+        for (wrapper, message) in rejected {
+            let err = wrapper.upsert(&rb, "users", &user).await.unwrap_err();
+            assert!(err.to_string().contains(message), "{}", err);
+        }
+        let err = QueryWrapper::new().upsert(&rb, "users", &vec![1]).await.unwrap_err();
+        assert!(err.to_string().contains("expects a struct or map"), "{}", err);
+        assert_eq!(rb.statements().len(), 1);
+    }
+
+    #[test]
+    fn chunk_delete_per_dialect() {
+        let wrapper = |dialect| {
+            QueryWrapper::new()
+                .dialect(dialect)
+                .inner_join("users u", "u.id = orders.user_id")
+                .eq("u.status", 0)
+        };
+        let (sql, params) = QueryWrapper::new().alias("o").eq("o.status", 0).build_chunk_delete("orders", 100);
+        assert_eq!(sql, "DELETE FROM orders AS o WHERE o.status = ? LIMIT 100");
+        assert_eq!(params, vec![Value::I64(0)]);
+        let (sql, _) = wrapper(Dialect::Postgres).build_chunk_delete("orders", 100);
+        assert_eq!(
+            sql,
+            "DELETE FROM orders WHERE orders.ctid IN (SELECT orders.ctid FROM orders INNER JOIN users u ON u.id = orders.user_id WHERE u.status = ? LIMIT 100)"
+        );
+        let (sql, _) = wrapper(Dialect::Sqlite).alias("o").build_chunk_delete("orders", 100);
+        assert_eq!(
+            sql,
+            "DELETE FROM orders WHERE orders.rowid IN (SELECT o.rowid FROM orders AS o INNER JOIN users u ON u.id = orders.user_id WHERE u.status = ? LIMIT 100)"
+        );
+        let (sql, _) = QueryWrapper::new().dialect(Dialect::Sqlite).eq("a", 1).build_chunk_delete("t x", 10);
+        assert_eq!(sql, "DELETE FROM t x WHERE x.rowid IN (SELECT x.rowid FROM t x WHERE a = ? LIMIT 10)");
+    }
+
+    #[tokio::test]
+    async fn chunk_delete_rejects_mysql_joins() {
+        let rb = MockExecutor::default();
+        let wrapper = QueryWrapper::new().inner_join("users u", "u.id = orders.user_id").eq("u.status", 0);
+        assert!(wrapper.delete_in_chunks(&rb, "orders", 100).await.is_err());
+        assert!(rb.statements().is_empty());
+    }
+
+    // 前 failures 次执行返回 error，之后成功
+    fn failing(failures: usize, error: &'static str) -> MockExecutor {
+        let calls = AtomicUsize::new(0);
+        MockExecutor::new(move |_, _| {
+            if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                return Err(Error::from(error));
+            }
+            Ok(Value::Array(vec![row("id", 1)]))
+        })
+    }
+
+    #[tokio::test]
+    async fn retry_policy_attempts() {
+        let policy = RetryPolicy::new(3, Duration::from_millis(1));
+        let wrapper = QueryWrapper::new().eq("id", 1).retry(policy.clone());
+
+        // 失败两次后第三次成功
+        let rb = failing(2, "Deadlock found when trying to get lock");
+        let rows: Vec<serde_json::Value> = wrapper.query(&rb, "t").await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rb.sqls().len(), 3);
+
+        // 三次都失败时返回最后一次的错误
+        let rb = failing(3, "Deadlock found when trying to get lock");
+        assert!(wrapper.query::<serde_json::Value>(&rb, "t").await.is_err());
+        assert_eq!(rb.sqls().len(), 3);
+
+        // 没有标记 idempotent 的删除不重试
+        let rb = failing(1, "Deadlock found when trying to get lock");
+        assert!(wrapper.clone().delete(&rb, "t").await.is_err());
+        assert_eq!(rb.sqls().len(), 1);
+
+        let rb = failing(1, "Deadlock found when trying to get lock");
+        wrapper.clone().idempotent().delete(&rb, "t").await.unwrap();
+        assert_eq!(rb.sqls().len(), 2);
+
+        // 不匹配的错误不重试
+        let rb = failing(1, "Duplicate entry '1' for key 'PRIMARY'");
+        assert!(wrapper.query::<serde_json::Value>(&rb, "t").await.is_err());
+        assert_eq!(rb.sqls().len(), 1);
+        let rb = failing(1, "Deadlock found when trying to get lock");
+        let custom = QueryWrapper::new().retry(policy.retry_on("1205"));
+        assert!(custom.query::<serde_json::Value>(&rb, "t").await.is_err());
+        assert_eq!(rb.sqls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn page_sql_matches_cloned_wrapper() {
+        let wrappers = [
+            QueryWrapper::new().eq("a", 1).order_by("id", false),
+            QueryWrapper::new().select(vec!["m.id"]).inner_join("orders o", "o.member_id = m.id").eq("o.total", 5),
+            QueryWrapper::new().custom_sql("SELECT * FROM t ORDER BY id").eq("a", 1),
+            QueryWrapper::new().group_by(vec!["a"]).having("COUNT(*) > ?", vec![Value::from(1)]),
+            QueryWrapper::new().eq("a", 1).union(QueryWrapper::new().eq("b", 2), "t2"),
+            QueryWrapper::new().dialect(Dialect::Postgres).eq("a", 1).with("x", &QueryWrapper::new().eq("c", 3), "t3"),
+            QueryWrapper::new().dialect(Dialect::Sqlite).eq("a", 1).max_limit(15),
+        ];
+        for wrapper in wrappers {
+            let rb = MockExecutor::new(|sql, _| {
+                if sql.contains("COUNT(*)") {
+                    return Ok(Value::Array(vec![row("count", 100)]));
+                }
+                Ok(Value::Array(vec![]))
+            });
+            let _: Page<serde_json::Value> = wrapper.page(&rb, "member m", 3, 20).await.unwrap();
+            let paged = wrapper.clone().limit(wrapper.cap_limit(20)).offset(2 * wrapper.cap_limit(20));
+            assert_eq!(
+                rb.statements(),
+                vec![
+                    (wrapper.build_count_sql("member m"), wrapper.build_count_params()),
+                    (paged.build_sql("member m"), paged.build_params()),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_queries_lazily() {
+        use futures::StreamExt;
+
+        let rb = id_table(100);
+        let wrapper = QueryWrapper::new();
+        let first: Vec<serde_json::Value> = wrapper.stream(&rb, "t", 10).take(10).map(Result::unwrap).collect().await;
+        assert_eq!(first.len(), 10);
+        assert_eq!(rb.sqls(), vec!["SELECT * FROM t LIMIT 0, 10"]);
+
+        let rb = id_table(100);
+        let ids: Vec<serde_json::Value> = wrapper.stream(&rb, "t", 10).take(15).map(Result::unwrap).collect().await;
+        assert_eq!(ids[14]["id"], 14);
+        assert_eq!(rb.sqls().len(), 2);
+
+        let rb = id_table(25);
+        let stream = wrapper.iter_pages_by_key(&rb, "t", 10, "id", true).into_stream::<serde_json::Value>();
+        assert_eq!(stream.count().await, 25);
+        assert_eq!(rb.sqls().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn iter_pages_walks_three_pages() {
+        let rb = id_table(25);
+        let wrapper = QueryWrapper::new().eq("status", 1);
+        let mut pages = wrapper.iter_pages(&rb, "t", 10);
+        let mut sizes = vec![];
+        while let Some(records) = pages.next_page::<serde_json::Value>().await.unwrap() {
+            sizes.push(records.len());
+        }
+        assert_eq!(sizes, vec![10, 10, 5]);
+        assert!(pages.next_page::<serde_json::Value>().await.unwrap().is_none());
+        assert_eq!(
+            rb.sqls(),
+            vec![
+                "SELECT * FROM t WHERE status = ? LIMIT 0, 10",
+                "SELECT * FROM t WHERE status = ? LIMIT 10, 10",
+                "SELECT * FROM t WHERE status = ? LIMIT 20, 10",
+            ]
+        );
+
+        let rb = id_table(25);
+        let mut pages = wrapper.iter_pages_by_key(&rb, "t", 10, "id", true);
+        let mut sizes = vec![];
+        let mut last_ids = vec![];
+        while let Some(records) = pages.next_page::<serde_json::Value>().await.unwrap() {
+            sizes.push(records.len());
+            last_ids.push(records.last().unwrap()["id"].as_u64().unwrap());
+        }
+        assert_eq!(sizes, vec![10, 10, 5]);
+        assert_eq!(last_ids, vec![9, 19, 24]);
+        let statements = rb.statements();
+        assert_eq!(statements[0], ("SELECT * FROM t WHERE status = ? ORDER BY id ASC LIMIT 11".to_string(), vec![Value::I64(1)]));
+        assert_eq!(
+            statements[2],
+            ("SELECT * FROM t WHERE status = ? AND id > ? ORDER BY id ASC LIMIT 11".to_string(), vec![Value::I64(1), Value::U64(19)])
+        );
+        assert_eq!(statements.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn deep_offset_queries_ids_then_rows() {
+        let handler = |ids: Vec<i64>| {
+            move |sql: &str, params: &[Value]| {
+                if sql.contains("COUNT(*)") {
+                    return Ok(Value::Array(vec![row("count", 100)]));
+                }
+                if sql.starts_with("SELECT id FROM") {
+                    return Ok(Value::Array(ids.iter().map(|id| row("id", *id)).collect()));
+                }
+                Ok(Value::Array(params.iter().map(|id| row("id", id.clone())).collect()))
+            }
+        };
+        let wrapper = QueryWrapper::new()
+            .dialect(Dialect::Postgres)
+            .eq("status", 1)
+            .order_by("created_at", false)
+            .optimize_deep_offset("id");
+
+        let rb = MockExecutor::new(handler(vec![42, 41]));
+        let page: Page<serde_json::Value> = wrapper.page(&rb, "t", 3, 2).await.unwrap();
+        assert_eq!(page.records.len(), 2);
+        let statements = rb.statements();
+        assert_eq!(
+            statements[1],
+            ("SELECT id FROM t WHERE status = ? ORDER BY created_at DESC LIMIT 2 OFFSET 4".to_string(), vec![Value::I64(1)])
+        );
+        assert_eq!(
+            statements[2],
+            (
+                "SELECT * FROM t WHERE id IN (?, ?) ORDER BY created_at DESC".to_string(),
+                vec![Value::I64(42), Value::I64(41)]
+            )
+        );
+
+        // 主键查询没有结果时不再查询整行
+        let rb = MockExecutor::new(handler(vec![]));
+        let page: Page<serde_json::Value> = wrapper.page(&rb, "t", 3, 2).await.unwrap();
+        assert!(page.records.is_empty());
+        assert_eq!(rb.sqls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn page_with_caps_requested_page_size() {
+        let req: PageRequest = serde_json::from_str(r#"{"page_size":100000}"#).unwrap();
+        assert_eq!((req.page_no, req.page_size, req.max_page_size), (1, 100000, 200));
+        let rb = id_table(1000);
+        let page: Page<serde_json::Value> = QueryWrapper::new().page_with(&rb, "t", &req).await.unwrap();
+        assert_eq!((page.page_size, page.current_size, page.pages), (200, 200, 5));
+        assert_eq!(sql_limit(&rb.sqls()[1]), 200);
+    }
+
+    #[tokio::test]
+    async fn page_json_uses_column_aliases() {
+        let rb = MockExecutor::new(|sql, _| {
+            if sql.contains("COUNT(*)") {
+                return Ok(Value::Array(vec![row("count", 1)]));
+            }
+            let mut map = rbs::value::map::ValueMap::new();
+            map.insert(Value::from("user_id"), Value::I64(7));
+            map.insert(Value::from("user_name"), Value::from("alice"));
+            Ok(Value::Array(vec![Value::Map(map)]))
+        });
+        let page = QueryWrapper::new()
+            .select(vec!["u.id AS user_id", "u.name AS user_name"])
+            .page_json(&rb, "users u", 1, 10)
+            .await
+            .unwrap();
+        assert_eq!(rb.sqls()[1], "SELECT u.id AS user_id, u.name AS user_name FROM users u LIMIT 0, 10");
+        assert_eq!(page.records, vec![serde_json::json!({"user_id": 7, "user_name": "alice"})]);
+        let keys: Vec<&String> = page.records[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["user_id", "user_name"]);
+    }
+
+    #[tokio::test]
+    async fn grouped_count_counts_groups() {
+        let ungrouped = QueryWrapper::new().gt("amount", 0);
+        let grouped = ungrouped.clone().select(vec!["status", "SUM(amount) AS total"]).group_by(vec!["status"]);
+        assert_eq!(ungrouped.build_count_sql("orders"), "SELECT COUNT(*) FROM orders WHERE amount > ?");
+        assert_eq!(
+            grouped.build_count_sql("orders"),
+            "SELECT COUNT(*) FROM (SELECT status, SUM(amount) AS total FROM orders WHERE amount > ? GROUP BY status) as t"
+        );
+
+        // 6 行数据分属 3 个 status，分组统计的是分组数
+        let statuses = [1, 1, 2, 2, 2, 3];
+        let rb = MockExecutor::new(move |sql, _| {
+            let total = if sql.contains("GROUP BY status") {
+                let mut distinct = statuses.to_vec();
+                distinct.dedup();
+                distinct.len()
+            } else {
+                statuses.len()
+            };
+            Ok(Value::Array(vec![row("count", total as u64)]))
+        });
+        assert_eq!(ungrouped.count(&rb, "orders").await.unwrap(), 6);
+        assert_eq!(grouped.count(&rb, "orders").await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn page_estimated_fallbacks() {
+        // 统计信息中的估算值为 estimate，实际有 25 行
+        let table = |estimate: i64| {
+            MockExecutor::new(move |sql, params| {
+                if sql.contains("TABLE_ROWS") || sql.contains("reltuples") {
+                    return Ok(Value::Array(vec![row("n", estimate)]));
+                }
+                id_rows(25, sql, params)
+            })
+        };
+        let counted = |rb: &MockExecutor| rb.sqls().iter().any(|sql| sql.starts_with("SELECT COUNT(*)"));
+
+        let rb = table(1000);
+        let page: Page<serde_json::Value> = QueryWrapper::new().page_estimated(&rb, "t", 1, 10).await.unwrap();
+        assert_eq!((page.total, page.pages, page.total_is_estimate, page.current_size), (1000, 100, true, 10));
+        assert!(!counted(&rb));
+
+        // 有条件时估算值不准确，使用 COUNT(*)
+        let rb = table(1000);
+        let page: Page<serde_json::Value> = QueryWrapper::new().eq("status", 1).page_estimated(&rb, "t", 1, 10).await.unwrap();
+        assert_eq!((page.total, page.total_is_estimate), (25, false));
+        assert_eq!(rb.sqls().len(), 2);
+        assert!(counted(&rb));
+
+        for (dialect, estimate) in [(Dialect::MySql, 0), (Dialect::Postgres, -1)] {
+            let rb = table(estimate);
+            let page: Page<serde_json::Value> = QueryWrapper::new().dialect(dialect).page_estimated(&rb, "t", 1, 10).await.unwrap();
+            assert_eq!((page.total, page.total_is_estimate), (25, false));
+            assert!(counted(&rb));
+        }
+
+        // SQLite 没有估算值，直接统计
+        let rb = table(1000);
+        let page: Page<serde_json::Value> = QueryWrapper::new().dialect(Dialect::Sqlite).page_estimated(&rb, "t", 1, 10).await.unwrap();
+        assert_eq!((page.total, page.total_is_estimate), (25, false));
+        assert_eq!(rb.sqls().len(), 2);
+    }
+
+    #[test]
+    fn with_recursive_sql_and_params() {
+        let anchor = QueryWrapper::new().select(vec!["id", "parent_id", "1 AS depth"]).eq("id", 1);
+        let wrapper = QueryWrapper::new()
+            .with_recursive("tree", &anchor, "category", "SELECT c.id, c.parent_id, tree.depth + 1 FROM category c JOIN tree ON c.parent_id = tree.id")
+            .gt("depth", 0)
+            .order_by("depth", true);
+        let cte = "WITH RECURSIVE tree AS (SELECT id, parent_id, 1 AS depth FROM category WHERE id = ? UNION ALL \
+                   SELECT c.id, c.parent_id, tree.depth + 1 FROM category c JOIN tree ON c.parent_id = tree.id) ";
+        assert_eq!(wrapper.build_sql("category"), format!("{}SELECT * FROM tree WHERE depth > ? ORDER BY depth ASC", cte));
+        assert_eq!(wrapper.build_count_sql("category"), format!("{}SELECT COUNT(*) FROM tree WHERE depth > ?", cte));
+        assert_eq!(wrapper.build_params(), vec![Value::I64(1), Value::I64(0)]);
+        assert_eq!(wrapper.build_count_params(), wrapper.build_params());
+
+        let step = QueryWrapper::new()
+            .select(vec!["c.id", "c.parent_id", "tree.depth + 1"])
+            .inner_join("tree", "c.parent_id = tree.id")
+            .lt("tree.depth", 3);
+        let wrapper = QueryWrapper::new()
+            .with_recursive_query("tree", &anchor, "category", &step, "category c")
+            .ne("id", 9);
+        assert_eq!(
+            wrapper.build_sql("category"),
+            "WITH RECURSIVE tree AS (SELECT id, parent_id, 1 AS depth FROM category WHERE id = ? UNION ALL \
+             SELECT c.id, c.parent_id, tree.depth + 1 FROM category c INNER JOIN tree ON c.parent_id = tree.id WHERE tree.depth < ?) \
+             SELECT * FROM tree WHERE id != ?"
+        );
+        // 参数顺序: anchor、step、当前查询
+        assert_eq!(wrapper.build_params(), vec![Value::I64(1), Value::I64(3), Value::I64(9)]);
+        assert_eq!(wrapper.build_count_params(), wrapper.build_params());
+    }
+
+    #[test]
+    fn order_by_raw_keeps_call_order() {
+        let wrapper = QueryWrapper::new()
+            .order_by("priority", false)
+            .order_by_raw("FIELD(status, 'open', 'closed')")
+            .order_by("id", true)
+            .order_by_raw("LENGTH(name) DESC");
+        assert_eq!(
+            wrapper.build_sql("tickets"),
+            "SELECT * FROM tickets ORDER BY priority DESC, FIELD(status, 'open', 'closed'), id ASC, LENGTH(name) DESC"
+        );
+        assert_eq!(wrapper.build_count_sql("tickets"), "SELECT COUNT(*) FROM tickets");
+    }
+
+    #[test]
+    fn order_by_forms_keep_call_order() {
+        let wrapper = QueryWrapper::new()
+            .order_by_desc("created_at")
+            .order_by_multi(&[("level", true), ("score", false)])
+            .order_by_asc("id")
+            .order_by_dir("name", Order::Desc);
+        assert_eq!(
+            wrapper.build_sql("member"),
+            "SELECT * FROM member ORDER BY created_at DESC, level ASC, score DESC, id ASC, name DESC"
+        );
+        assert_eq!(QueryWrapper::new().order_by_multi(&[]).build_sql("member"), "SELECT * FROM member");
+    }
+
+    #[tokio::test]
+    async fn alias_in_query_and_page() {
+        let wrapper = QueryWrapper::new()
+            .alias("m")
+            .select(vec!["m.id", "o.total"])
+            .left_join("orders o", "o.member_id = m.id")
+            .eq("m.status", 1)
+            .order_by("m.id", true);
+        assert_eq!(
+            wrapper.build_sql("member"),
+            "SELECT m.id, o.total FROM member AS m LEFT JOIN orders o ON o.member_id = m.id WHERE m.status = ? ORDER BY m.id ASC"
+        );
+        assert_eq!(
+            wrapper.build_count_sql("member"),
+            "SELECT COUNT(*) FROM member AS m LEFT JOIN orders o ON o.member_id = m.id WHERE m.status = ?"
+        );
+
+        let rb = id_table(3);
+        let rows: Vec<serde_json::Value> = wrapper.query(&rb, "member").await.unwrap();
+        let page: Page<serde_json::Value> = wrapper.page(&rb, "member", 1, 10).await.unwrap();
+        assert_eq!((rows.len(), page.total, page.current_size), (3, 3, 3));
+        let sqls = rb.sqls();
+        assert!(sqls.iter().all(|sql| sql.contains("FROM member AS m LEFT JOIN")), "{:?}", sqls);
+        assert_eq!(sqls[1], "SELECT COUNT(*) FROM member AS m LEFT JOIN orders o ON o.member_id = m.id WHERE m.status = ?");
+        assert!(sqls[2].ends_with("ORDER BY m.id ASC LIMIT 0, 10"), "{}", sqls[2]);
+    }
+
+    #[tokio::test]
+    async fn table_suffix_applies_to_every_statement() {
+        let wrapper = QueryWrapper::new()
+            .table_suffix("_202401")
+            .inner_join("{table}_detail d", "d.order_id = o.id")
+            .eq("o.status", 1);
+        let rb = id_table(3);
+        let _: Vec<serde_json::Value> = wrapper.query(&rb, "orders o").await.unwrap();
+        let _: Page<serde_json::Value> = wrapper.page(&rb, "orders o", 1, 10).await.unwrap();
+        QueryWrapper::new().table_suffix("_202401").eq("status", 0).delete(&rb, "orders").await.unwrap();
+        assert_eq!(
+            rb.sqls(),
+            vec![
+                "SELECT * FROM orders_202401 o INNER JOIN orders_202401_detail d ON d.order_id = o.id WHERE o.status = ?",
+                "SELECT COUNT(*) FROM orders_202401 o INNER JOIN orders_202401_detail d ON d.order_id = o.id WHERE o.status = ?",
+                "SELECT * FROM orders_202401 o INNER JOIN orders_202401_detail d ON d.order_id = o.id WHERE o.status = ? LIMIT 0, 10",
+                "DELETE FROM orders_202401 WHERE status = ?",
+            ]
+        );
+        assert!(QueryWrapper::new().table_suffix("_1; DROP").validate().is_err());
+
+        let rb = id_table(2);
+        let rows: Vec<serde_json::Value> = QueryWrapper::new().eq("status", 1).query_sharded(&rb, "orders", &["_0", "_1", "_2"]).await.unwrap();
+        assert_eq!(rows.len(), 6);
+        assert_eq!(
+            rb.sqls(),
+            vec![
+                "SELECT * FROM orders_0 WHERE status = ?",
+                "SELECT * FROM orders_1 WHERE status = ?",
+                "SELECT * FROM orders_2 WHERE status = ?",
+            ]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use rbatis::RBatis;
+use rbatis_wrapper::{DbRouter, Dialect, QueryWrapper};
+use rbdc_sqlite::driver::SqliteDriver;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct User {
+    id: i64,
+    name: String,
+    status: i64,
+}
+
+// 每个测试使用独立的内存数据库，连接池限制为一个连接，保证所有语句看到同一份数据
+async fn sqlite() -> RBatis {
+    let rb = RBatis::new();
+    rb.init(SqliteDriver {}, "sqlite://:memory:").unwrap();
+    rb.get_pool().unwrap().set_max_open_conns(1).await;
+    rb.exec("CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL, status INTEGER NOT NULL)", vec![])
+        .await
+        .unwrap();
+    rb.exec(
+        "INSERT INTO users (id, name, status) VALUES (1, 'alice', 0), (2, 'bob', 1), (3, 'carol', 1)",
+        vec![],
+    )
+    .await
+    .unwrap();
+    rb
+}
+
+async fn user_count(rb: &RBatis) -> u64 {
+    QueryWrapper::new().dialect(Dialect::Sqlite).count(rb, "users").await.unwrap()
+}
+
+#[tokio::test]
+async fn select_and_delete_in_rolled_back_transaction() {
+    let rb = sqlite().await;
+    let tx = rb.acquire_begin().await.unwrap();
+    let wrapper = QueryWrapper::new().eq("status", 1);
+    let users: Vec<User> = wrapper.query(&tx, "users").await.unwrap();
+    assert_eq!(users.len(), 2);
+    let result = wrapper.clone().delete(&tx, "users").await.unwrap();
+    assert_eq!(result.rows_affected, 2);
+    assert_eq!(QueryWrapper::new().count(&tx, "users").await.unwrap(), 1);
+    tx.rollback().await.unwrap();
+    // 归还唯一的连接，后续查询才能拿到它
+    drop(tx);
+
+    assert_eq!(user_count(&rb).await, 3);
+    let bob: Option<User> = QueryWrapper::new().eq("id", 2).get_one(&rb, "users").await.unwrap();
+    assert_eq!(bob.unwrap().name, "bob");
+}
+
+#[tokio::test]
+async fn transaction_rolls_back_when_closure_fails() {
+    let rb = sqlite().await;
+    let result: Result<(), rbatis::Error> = QueryWrapper::transaction(&rb, |tx| async move {
+        QueryWrapper::new().eq("id", 1).delete(&tx, "users").await?;
+        QueryWrapper::new().eq("id", 2).delete(&tx, "users").await?;
+        Err(rbatis::Error::from("abort"))
+    })
+    .await;
+    assert_eq!(result.unwrap_err().to_string(), "abort");
+    assert_eq!(user_count(&rb).await, 3);
+
+    let remaining: Vec<User> = QueryWrapper::transaction(&rb, |tx| async move {
+        QueryWrapper::new().eq("id", 1).delete(&tx, "users").await?;
+        QueryWrapper::new().order_by("id", true).query(&tx, "users").await
+    })
+    .await
+    .unwrap();
+    assert_eq!(remaining.iter().map(|u| u.id).collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(user_count(&rb).await, 2);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Category {
+    id: i64,
+    depth: i64,
+}
+
+#[tokio::test]
+async fn with_recursive_reads_two_level_subtree() {
+    let rb = sqlite().await;
+    rb.exec("CREATE TABLE category (id INTEGER PRIMARY KEY, parent_id INTEGER)", vec![]).await.unwrap();
+    // 1 -> (2, 3), 2 -> 4；5 是另一棵树
+    rb.exec("INSERT INTO category (id, parent_id) VALUES (1, NULL), (2, 1), (3, 1), (4, 2), (5, NULL)", vec![])
+        .await
+        .unwrap();
+
+    let anchor = QueryWrapper::new().select(vec!["id", "0 AS depth"]).eq("id", 1);
+    let step = QueryWrapper::new()
+        .select(vec!["c.id", "tree.depth + 1"])
+        .inner_join("tree", "c.parent_id = tree.id");
+    let subtree = QueryWrapper::new()
+        .dialect(Dialect::Sqlite)
+        .with_recursive_query("tree", &anchor, "category", &step, "category c")
+        .order_by("id", true);
+    let rows: Vec<Category> = subtree.query(&rb, "category").await.unwrap();
+    let rows: Vec<(i64, i64)> = rows.into_iter().map(|c| (c.id, c.depth)).collect();
+    assert_eq!(rows, vec![(1, 0), (2, 1), (3, 1), (4, 2)]);
+    assert_eq!(subtree.count(&rb, "category").await.unwrap(), 4);
+
+    let raw = QueryWrapper::new()
+        .dialect(Dialect::Sqlite)
+        .with_recursive("tree", &anchor, "category", "SELECT c.id, tree.depth + 1 FROM category c JOIN tree ON c.parent_id = tree.id")
+        .eq("depth", 1);
+    assert_eq!(raw.count(&rb, "category").await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn router_sends_reads_to_replicas_and_writes_to_primary() {
+    let mut instances = vec![];
+    for name in ["primary", "replica1", "replica2"] {
+        let rb = sqlite().await;
+        // 用 id = 1 的 name 区分实例
+        rb.exec("UPDATE users SET name = ? WHERE id = 1", vec![name.into()]).await.unwrap();
+        instances.push(rb);
+    }
+    let primary = instances.remove(0);
+    let router = DbRouter::new(primary, instances);
+    let first = QueryWrapper::new().eq("id", 1);
+
+    let mut seen = vec![];
+    for _ in 0..3 {
+        let user: Option<User> = first.get_one(&router, "users").await.unwrap();
+        seen.push(user.unwrap().name);
+    }
+    assert_eq!(seen, vec!["replica1", "replica2", "replica1"]);
+
+    let result = QueryWrapper::new().eq("status", 1).delete(&router, "users").await.unwrap();
+    assert_eq!(result.rows_affected, 2);
+    assert_eq!(user_count(router.writer()).await, 1);
+    assert_eq!(QueryWrapper::new().count(&router, "users").await.unwrap(), 3);
+    assert_eq!(QueryWrapper::new().count(&router, "users").await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn upsert_updates_or_keeps_existing_rows() {
+    let rb = sqlite().await;
+    let wrapper = QueryWrapper::new().dialect(Dialect::Sqlite).on_conflict(vec!["id"]);
+    let dave = User { id: 4, name: "dave".to_string(), status: 0 };
+    assert_eq!(wrapper.upsert(&rb, "users", &dave).await.unwrap().rows_affected, 1);
+    let alice = User { id: 1, name: "alice2".to_string(), status: 2 };
+    wrapper.clone().do_update(vec!["status"]).upsert(&rb, "users", &alice).await.unwrap();
+    let bob = User { id: 2, name: "bob2".to_string(), status: 2 };
+    wrapper.clone().do_nothing().upsert(&rb, "users", &bob).await.unwrap();
+
+    let users: Vec<User> = QueryWrapper::new().order_by("id", true).query(&rb, "users").await.unwrap();
+    let users: Vec<(i64, &str, i64)> = users.iter().map(|u| (u.id, u.name.as_str(), u.status)).collect();
+    assert_eq!(users, vec![(1, "alice", 2), (2, "bob", 1), (3, "carol", 1), (4, "dave", 0)]);
+}